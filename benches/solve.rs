@@ -0,0 +1,48 @@
+//! Time of `Simulation::solve` on particles stored in random order versus sorted along
+//! the Z-order curve. Run with `cargo bench --bench solve`.
+
+use std::time::{Duration, Instant};
+
+use glam::vec2;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use verlet_integration::solver::{Constraint, Simulation, PARTICLE_SIZE};
+use verlet_integration::Particle;
+
+const SIDE: usize = 340; // particles per side of the square block, about 115k in total
+const STEPS: usize = 40;
+
+/// A packed block of particles in shuffled memory order, as after a long run of spawning.
+fn shuffled_block() -> Simulation {
+    let spacing = 2. * PARTICLE_SIZE;
+    let mut particles: Vec<Particle> = (0..SIDE * SIDE)
+        .map(|k| Particle::null().place(vec2((k % SIDE) as f32, (k / SIDE) as f32) * spacing + vec2(-60., -40.)))
+        .collect();
+    particles.shuffle(&mut ChaCha8Rng::seed_from_u64(0));
+    Simulation::new(Constraint::Box(vec2(-65., -45.), vec2(65., 45.)), spacing, &particles, &[]).with_seed(0)
+}
+
+fn time_steps(simulation: &mut Simulation) -> Duration {
+    let dt = simulation.parameters.dt();
+    simulation.solve(dt); // warm up the grid allocations
+    let start = Instant::now();
+    for _ in 0..STEPS {
+        simulation.solve(dt);
+    }
+    start.elapsed() / STEPS as u32
+}
+
+fn main() {
+    let mut unsorted = shuffled_block();
+    let mut sorted = shuffled_block();
+    sorted.sort_particles();
+
+    let n = unsorted.particles.len();
+    let unsorted_time = time_steps(&mut unsorted);
+    let sorted_time = time_steps(&mut sorted);
+    println!("{n} particles, mean solve time over {STEPS} steps");
+    println!("  spawn order:   {unsorted_time:?}");
+    println!("  Z-order:       {sorted_time:?}");
+    println!("  speed-up:      {:.2}x", unsorted_time.as_secs_f64() / sorted_time.as_secs_f64());
+}
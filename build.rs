@@ -6,13 +6,17 @@ fn main() -> Result<()> {
     // This tells Cargo to rerun this script if something in /textures/ changes.
     println!("cargo::rerun-if-changed=textures/*");
 
+    // Textures are only needed by the viewer application.
+    if std::env::var_os("CARGO_FEATURE_GUI").is_none() {
+        return Ok(());
+    }
+
     let out_dir = std::path::Path::new("./target/release/");
+    std::fs::create_dir_all(out_dir)?;
     let mut copy_options = CopyOptions::new();
     copy_options.overwrite = true;
-    let mut paths_to_copy = Vec::new();
-    paths_to_copy.push("textures/");
+    let paths_to_copy = vec!["textures/"];
     copy_items(&paths_to_copy, out_dir, &copy_options)?;
 
     Ok(())
 }
- 
\ No newline at end of file
@@ -0,0 +1,403 @@
+//! Headless runner: steps a simulation without opening a window and dumps particle
+//! positions at chosen frames as CSV or JSON. Particles are identified by the slot and
+//! generation of their `ParticleId`, which stay the same when sorting or the kill plane
+//! reorders them.
+//!
+//! ```text
+//! verlet-cli --particles 10000 --frames 600 --dump-every 60 --format csv --output out.csv
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use anyhow::{anyhow, bail, Context, Result};
+use glam::{vec2, BVec2, Vec2};
+
+use verlet_integration::recording::{Input, Recorder, KEYFRAME_INTERVAL};
+use verlet_integration::solver::{self, BroadPhase, ConstraintOrder, Walls, PARTICLE_SIZE, SPAWN_MARGIN};
+use verlet_integration::{Constraint, MaterialRegistry, Simulation};
+
+const USAGE: &str = "\
+Usage: verlet-cli [OPTIONS]
+
+Options:
+  --scene <PATH>           load the initial state from a scene file instead of spawning particles
+  --save <PATH>            save the final state as a scene file (.json or binary)
+  --record <PATH>          record the run so it can be replayed and scrubbed in the viewer
+  --particles <N>          number of particles to spawn [default: 10000]
+  --seed <N>               seed for spawning, makes runs reproducible [default: random]
+  --bounds <X0,Y0,X1,Y1>   box constraint corners [default: -60,-10,60,40]
+  --open                   no walls, the bounds only mark the spawn area
+  --periodic <x|y|xy>      join opposite sides of the bounds on these axes instead of walls
+  --walls <SIDES>          container with walls only on the listed sides, e.g. left,right,bottom [default: all]
+  --kill-plane <Y>         remove particles that fall below this height
+  --materials <PATH>       material registry file [default: built-in materials]
+  --material <NAME>        material of spawned particles [default: the first one]
+  --cell-size <F>          broad phase cell size [default: 0.2]
+  --broad-phase <grid|hash>  dense grid or spatial hash of occupied cells [default: grid]
+  --frames <N>             number of frames to simulate [default: 600]
+  --sub-ticks <N>          solver steps per frame [default: 8, or the scene's]
+  --dt <F>                 simulated time per frame [default: 0.08, or the scene's]
+  --gravity <X,Y>          gravity acceleration [default: 0,-1, or the scene's]
+  --damping <F>            linear velocity damping [default: 10, or the scene's]
+  --iterations <N>         constraint relaxation passes per solver step [default: 1, or the scene's]
+  --order <sequential|random|coloured>  order of the constraint passes [default: sequential, or the scene's]
+  --sort-interval <N>      solver steps between spatial sorts of the particles, 0 never sorts [default: 0, or the scene's]
+  --dump-every <N>         dump every N-th frame
+  --dump-frames <A,B,..>   dump the listed frames (the last frame is dumped if none is chosen)
+  --format <csv|json>      output format [default: csv]
+  --output <PATH>          output file [default: stdout]
+  -h, --help               print this message";
+
+/// Options that only describe how particles are spawned, a loaded scene brings its own.
+const SPAWN_OPTIONS: &[&str] = &[
+    "--particles", "--seed", "--bounds", "--open", "--periodic", "--walls",
+    "--materials", "--material", "--cell-size", "--broad-phase",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+struct Options {
+    scene: Option<String>,
+    save: Option<String>,
+    record: Option<String>,
+    particles: usize,
+    seed: Option<u64>,
+    bounds: (f32, f32, f32, f32),
+    open: bool,
+    periodic: BVec2,
+    walls: Option<Walls>,
+    kill_plane: Option<f32>,
+    materials: Option<String>,
+    material: Option<String>,
+    cell_size: f32,
+    broad_phase: BroadPhase,
+    frames: usize,
+    sub_ticks: Option<usize>,
+    dt: Option<f32>,
+    gravity: Option<Vec2>,
+    damping: Option<f32>,
+    iterations: Option<usize>,
+    order: Option<ConstraintOrder>,
+    sort_interval: Option<usize>,
+    dump_every: Option<usize>,
+    dump_frames: Vec<usize>,
+    format: Format,
+    output: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            scene: None,
+            save: None,
+            record: None,
+            particles: 10000,
+            seed: None,
+            bounds: (-60., -10., 60., 40.),
+            open: false,
+            periodic: BVec2::FALSE,
+            walls: None,
+            kill_plane: None,
+            materials: None,
+            material: None,
+            cell_size: 2. * PARTICLE_SIZE,
+            broad_phase: BroadPhase::Grid,
+            frames: 600,
+            sub_ticks: None,
+            dt: None,
+            gravity: None,
+            damping: None,
+            iterations: None,
+            order: None,
+            sort_interval: None,
+            dump_every: None,
+            dump_frames: Vec::new(),
+            format: Format::Csv,
+            output: None,
+        }
+    }
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>> {
+        let mut options = Options::default();
+        let mut spawn_options = Vec::new();
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                return Ok(None);
+            }
+            if SPAWN_OPTIONS.contains(&arg.as_str()) {
+                spawn_options.push(arg.clone());
+            }
+            let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+            match arg.as_str() {
+                "--scene" => options.scene = Some(value()?),
+                "--save" => options.save = Some(value()?),
+                "--record" => options.record = Some(value()?),
+                "--particles" => options.particles = parse_number(&value()?)?,
+                "--seed" => options.seed = Some(parse_number(&value()?)?),
+                "--bounds" => {
+                    let v: Vec<f32> = parse_list(&value()?)?;
+                    if v.len() != 4 || v[0] >= v[2] || v[1] >= v[3] {
+                        bail!("--bounds expects X0,Y0,X1,Y1 with X0 < X1 and Y0 < Y1");
+                    }
+                    options.bounds = (v[0], v[1], v[2], v[3]);
+                }
+                "--open" => options.open = true,
+                "--periodic" => {
+                    options.periodic = match value()?.as_str() {
+                        "x" => BVec2::new(true, false),
+                        "y" => BVec2::new(false, true),
+                        "xy" => BVec2::TRUE,
+                        other => bail!("unknown periodic axes {other:?}, expected x, y or xy"),
+                    }
+                }
+                "--walls" => {
+                    let mut walls = Walls::NONE;
+                    for side in value()?.split(',').filter(|side| !side.is_empty()) {
+                        match side.trim() {
+                            "left" => walls.left = true,
+                            "right" => walls.right = true,
+                            "bottom" => walls.bottom = true,
+                            "top" => walls.top = true,
+                            other => bail!("unknown side {other:?}, expected left, right, bottom or top"),
+                        }
+                    }
+                    options.walls = Some(walls);
+                }
+                "--kill-plane" => options.kill_plane = Some(parse_number(&value()?)?),
+                "--materials" => options.materials = Some(value()?),
+                "--material" => options.material = Some(value()?),
+                "--cell-size" => options.cell_size = parse_number(&value()?)?,
+                "--broad-phase" => {
+                    options.broad_phase = match value()?.as_str() {
+                        "grid" => BroadPhase::Grid,
+                        "hash" => BroadPhase::SpatialHash,
+                        other => bail!("unknown broad phase {other:?}, expected grid or hash"),
+                    }
+                }
+                "--frames" => options.frames = parse_number(&value()?)?,
+                "--sub-ticks" => options.sub_ticks = Some(parse_number(&value()?)?),
+                "--dt" => options.dt = Some(parse_number(&value()?)?),
+                "--gravity" => {
+                    let v: Vec<f32> = parse_list(&value()?)?;
+                    if v.len() != 2 {
+                        bail!("--gravity expects X,Y");
+                    }
+                    options.gravity = Some(vec2(v[0], v[1]));
+                }
+                "--damping" => options.damping = Some(parse_number(&value()?)?),
+                "--iterations" => options.iterations = Some(parse_number(&value()?)?),
+                "--order" => {
+                    options.order = Some(match value()?.as_str() {
+                        "sequential" => ConstraintOrder::Sequential,
+                        "random" => ConstraintOrder::Randomized,
+                        "coloured" => ConstraintOrder::Coloured,
+                        other => bail!("unknown order {other:?}, expected sequential, random or coloured"),
+                    })
+                }
+                "--sort-interval" => options.sort_interval = Some(parse_number(&value()?)?),
+                "--dump-every" => options.dump_every = Some(parse_number(&value()?)?),
+                "--dump-frames" => options.dump_frames = parse_list(&value()?)?,
+                "--format" => {
+                    options.format = match value()?.as_str() {
+                        "csv" => Format::Csv,
+                        "json" => Format::Json,
+                        other => bail!("unknown format {other:?}, expected csv or json"),
+                    }
+                }
+                "--output" => options.output = Some(value()?),
+                _ => bail!("unknown argument {arg:?}\n\n{USAGE}"),
+            }
+        }
+
+        if options.particles > solver::MAX as usize {
+            bail!("--particles must not exceed {}", solver::MAX);
+        }
+        if options.sub_ticks == Some(0) || options.iterations == Some(0) || options.cell_size <= 0. {
+            bail!("--sub-ticks, --iterations and --cell-size must be positive");
+        }
+        if [options.open, options.periodic.any(), options.walls.is_some()].iter().filter(|&&set| set).count() > 1 {
+            bail!("--open, --periodic and --walls exclude each other");
+        }
+        if options.scene.is_some() && !spawn_options.is_empty() {
+            bail!("{} only apply to spawned particles, a scene brings its own", spawn_options.join(", "));
+        }
+        let (x0, y0, x1, y1) = options.bounds;
+        if options.scene.is_none() && options.particles > 0 && (x1 - x0).min(y1 - y0) <= 2. * SPAWN_MARGIN {
+            bail!("--bounds must be wider and taller than {} to spawn particles", 2. * SPAWN_MARGIN);
+        }
+        if options.dump_every == Some(0) {
+            bail!("--dump-every must be positive");
+        }
+        Ok(Some(options))
+    }
+
+    fn should_dump(&self, frame: usize) -> bool {
+        if self.dump_every.is_none() && self.dump_frames.is_empty() {
+            return frame == self.frames;
+        }
+        self.dump_every.is_some_and(|n| frame.is_multiple_of(n)) || self.dump_frames.contains(&frame)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    s.trim().parse().with_context(|| format!("invalid number {s:?}"))
+}
+
+fn parse_list<T: std::str::FromStr>(s: &str) -> Result<Vec<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    s.split(',').map(parse_number).collect()
+}
+
+/// Writes particle state of the dumped frames in the chosen format.
+struct Dumper<W: Write> {
+    out: W,
+    format: Format,
+    dumped: usize,
+}
+
+impl<W: Write> Dumper<W> {
+    fn new(mut out: W, format: Format) -> Result<Self> {
+        match format {
+            Format::Csv => writeln!(out, "frame,slot,generation,x,y")?,
+            Format::Json => write!(out, "[")?,
+        }
+        Ok(Self { out, format, dumped: 0 })
+    }
+
+    fn dump(&mut self, frame: usize, simulation: &Simulation) -> Result<()> {
+        match self.format {
+            Format::Csv => {
+                for (i, p) in simulation.particles.iter().enumerate() {
+                    let id = simulation.id_of(i).context("particle without an id")?;
+                    writeln!(self.out, "{frame},{},{},{},{}", id.slot, id.generation, p.pos.x, p.pos.y)?;
+                }
+            }
+            Format::Json => {
+                if self.dumped > 0 {
+                    write!(self.out, ",")?;
+                }
+                write!(self.out, "\n{{\"frame\":{frame},\"particles\":[")?;
+                for (i, p) in simulation.particles.iter().enumerate() {
+                    if i > 0 {
+                        write!(self.out, ",")?;
+                    }
+                    let id = simulation.id_of(i).context("particle without an id")?;
+                    write!(self.out, "[{},{},{},{}]", id.slot, id.generation, p.pos.x, p.pos.y)?;
+                }
+                write!(self.out, "]}}")?;
+            }
+        }
+        self.dumped += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        if self.format == Format::Json {
+            writeln!(self.out, "\n]")?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+fn run(options: &Options) -> Result<()> {
+    let mut simulation = match &options.scene {
+        Some(path) => Simulation::load(path)?,
+        None => {
+            let (x0, y0, x1, y1) = options.bounds;
+            let (bl, tr) = (vec2(x0, y0), vec2(x1, y1));
+            let constraint = if options.open {
+                Constraint::Open(bl, tr)
+            } else if options.periodic.any() {
+                Constraint::Periodic(bl, tr, options.periodic)
+            } else if let Some(walls) = options.walls {
+                Constraint::Container(bl, tr, walls)
+            } else {
+                Constraint::Box(bl, tr)
+            };
+            let mut simulation = Simulation::new(constraint, options.cell_size, &[], &[])
+                .with_broad_phase(options.broad_phase);
+            if let Some(seed) = options.seed {
+                simulation = simulation.with_seed(seed);
+            }
+            if let Some(path) = &options.materials {
+                simulation = simulation.with_materials(MaterialRegistry::load(path)?);
+            }
+            if let Some(name) = &options.material {
+                simulation.spawn_material = simulation
+                    .materials
+                    .id(name)
+                    .ok_or_else(|| anyhow!("unknown material {name:?}"))?;
+            }
+            simulation.change_number(options.particles);
+            simulation
+        }
+    };
+
+    let out: Box<dyn Write> = match &options.output {
+        Some(path) => Box::new(File::create(path).with_context(|| format!("cannot create {path}"))?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut dumper = Dumper::new(BufWriter::new(out), options.format)?;
+
+    if options.should_dump(0) {
+        dumper.dump(0, &simulation)?;
+    }
+    let parameters = &mut simulation.parameters;
+    parameters.sub_ticks = options.sub_ticks.unwrap_or(parameters.sub_ticks);
+    parameters.frame_dt = options.dt.unwrap_or(parameters.frame_dt);
+    parameters.gravity = options.gravity.unwrap_or(parameters.gravity);
+    parameters.damping = options.damping.unwrap_or(parameters.damping);
+    parameters.iterations = options.iterations.unwrap_or(parameters.iterations);
+    parameters.constraint_order = options.order.unwrap_or(parameters.constraint_order);
+    parameters.sort_interval = options.sort_interval.unwrap_or(parameters.sort_interval);
+    simulation.kill_plane = options.kill_plane.or(simulation.kill_plane);
+
+    let mut recorder = match &options.record {
+        Some(_) => Some(Recorder::new(&simulation, KEYFRAME_INTERVAL)?),
+        None => None,
+    };
+    let dt = simulation.parameters.dt();
+    for frame in 1..=options.frames {
+        for _ in 0..simulation.parameters.sub_ticks {
+            match &mut recorder {
+                Some(recorder) => recorder.record(&mut simulation, Input::Solve(dt))?,
+                None => simulation.solve(dt),
+            }
+        }
+        if options.should_dump(frame) {
+            dumper.dump(frame, &simulation)?;
+        }
+    }
+    dumper.finish()?;
+
+    if let Some(path) = &options.save {
+        simulation.save(path)?;
+    }
+    if let (Some(recorder), Some(path)) = (recorder, &options.record) {
+        recorder.finish().save(path)?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    match Options::parse(std::env::args().skip(1))? {
+        Some(options) => run(&options),
+        None => {
+            println!("{USAGE}");
+            Ok(())
+        }
+    }
+}
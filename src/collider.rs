@@ -0,0 +1,265 @@
+//! Static world geometry tested against every particle after the [`Constraint`](crate::Constraint).
+//!
+//! Each [`Collider`] is a signed distance function, negative inside the shape. A particle
+//! touching or penetrating a collider is moved back out along the gradient of the distance
+//! by the penetration depth and loses velocity like on the walls of the box.
+//!
+//! Shapes are given in the local space of the collider, which is placed by its `position`
+//! and `rotation`. Kinematic colliders advance them by their velocities every step and carry
+//! touching particles along, conveyor belts only move their surface.
+
+use anyhow::{ensure, Result};
+use glam::{Mat2, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::sdf::{DistanceField, Sdf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Shape {
+    Circle { center: Vec2, radius: f32 },
+    /// Closed polygon, convex or concave, with vertices in any winding order.
+    Polygon(Vec<Vec2>),
+    /// Open chain of segments that particles can't cross from either side, particles that
+    /// moved past it within a step are put back on the side they came from.
+    Polyline(Vec<Vec2>),
+    /// Precomputed signed distances, see [`DistanceField`].
+    Field(DistanceField),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Collider {
+    pub shape: Shape,
+    #[serde(default)]
+    pub inside: bool, // particles are kept inside the shape instead of outside, not for polylines
+    #[serde(default)]
+    pub position: Vec2, // where the origin of the shape is in the world
+    #[serde(default)]
+    pub rotation: f32, // counter-clockwise, in radians
+    #[serde(default)]
+    pub velocity: Vec2, // per second
+    #[serde(default)]
+    pub angular_velocity: f32, // radians per second, counter-clockwise
+    #[serde(default)]
+    pub surface_speed: f32, // conveyor belt speed, positive moves particles resting on top of the surface to the right
+}
+
+impl Collider {
+    pub fn new(shape: Shape) -> Self {
+        Self {
+            shape,
+            inside: false,
+            position: Vec2::ZERO,
+            rotation: 0.,
+            velocity: Vec2::ZERO,
+            angular_velocity: 0.,
+            surface_speed: 0.,
+        }
+    }
+
+    pub fn circle(center: Vec2, radius: f32) -> Self {
+        Self::new(Shape::Circle { center, radius })
+    }
+
+    pub fn polygon(vertices: impl Into<Vec<Vec2>>) -> Self {
+        Self::new(Shape::Polygon(vertices.into()))
+    }
+
+    pub fn polyline(vertices: impl Into<Vec<Vec2>>) -> Self {
+        Self::new(Shape::Polyline(vertices.into()))
+    }
+
+    pub fn field(field: DistanceField) -> Result<Self> {
+        field.validate()?;
+        Ok(Self::new(Shape::Field(field)))
+    }
+
+    /// Bakes `sdf` into a field covering the rectangle from `bl` to `tr`.
+    pub fn sdf(sdf: &Sdf, bl: Vec2, tr: Vec2, cell_size: f32) -> Result<Self> {
+        Self::field(DistanceField::from_sdf(sdf, bl, tr, cell_size)?)
+    }
+
+    /// Keeps particles inside the shape, e.g. a round bowl instead of a round obstacle.
+    pub fn with_inside(mut self) -> Self {
+        self.inside = true;
+        self
+    }
+
+    pub fn with_transform(mut self, position: Vec2, rotation: f32) -> Self {
+        self.position = position;
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_velocity(mut self, velocity: Vec2, angular_velocity: f32) -> Self {
+        self.velocity = velocity;
+        self.angular_velocity = angular_velocity;
+        self
+    }
+
+    pub fn with_surface_speed(mut self, surface_speed: f32) -> Self {
+        self.surface_speed = surface_speed;
+        self
+    }
+
+    /// Sets the velocities that bring the collider to `position` and `rotation` in `duration`,
+    /// call it every frame with the next keyframe of an animation.
+    pub fn animate_to(&mut self, position: Vec2, rotation: f32, duration: f32) {
+        self.velocity = (position - self.position) / duration;
+        self.angular_velocity = (rotation - self.rotation) / duration;
+    }
+
+    /// Moves the collider by its velocities.
+    pub fn advance(&mut self, dt: f32) {
+        self.position += self.velocity * dt;
+        self.rotation += self.angular_velocity * dt;
+    }
+
+    /// Velocity of the surface at the world position `pos` with the outward `normal`.
+    pub fn surface_velocity(&self, pos: Vec2, normal: Vec2) -> Vec2 {
+        let r = pos - self.position;
+        self.velocity + r.perp() * self.angular_velocity - normal.perp() * self.surface_speed
+    }
+
+    /// Distance from the world position `pos` to the boundary, negative inside the shape,
+    /// and the outward unit normal of the boundary at the closest point.
+    pub fn signed_distance(&self, pos: Vec2) -> (f32, Vec2) {
+        let rotation = Mat2::from_angle(self.rotation);
+        let (distance, normal) = self.local_distance(rotation.transpose() * (pos - self.position));
+        (distance, rotation * normal)
+    }
+
+    fn local_distance(&self, pos: Vec2) -> (f32, Vec2) {
+        match &self.shape {
+            Shape::Circle { center, radius } => {
+                let d = pos - *center;
+                (d.length() - radius, d.try_normalize().unwrap_or(Vec2::Y))
+            }
+            Shape::Polygon(vertices) => {
+                let (distance, closest, edge) = closest_on_segments(vertices, true, pos);
+                let inside = contains(vertices, pos);
+                let normal = if distance > 0. {
+                    (pos - closest) / distance * if inside { -1. } else { 1. }
+                } else {
+                    // on the boundary, the edge normal pointing away from the polygon
+                    edge.perp().normalize_or_zero() * -signed_area(vertices).signum()
+                };
+                (if inside { -distance } else { distance }, normal)
+            }
+            Shape::Polyline(vertices) => {
+                let (distance, closest, edge) = closest_on_segments(vertices, false, pos);
+                let normal = if distance > 0. { (pos - closest) / distance } else { edge.perp().normalize_or_zero() };
+                (distance, normal)
+            }
+            Shape::Field(field) => field.sample(pos),
+        }
+    }
+
+    /// Direction in which a particle of `radius` at `pos` has to be moved out of the collider
+    /// and by how much, `None` if they don't touch.
+    pub fn contact(&self, pos: Vec2, radius: f32) -> Option<(Vec2, f32)> {
+        let (distance, normal) = self.signed_distance(pos);
+        let (depth, normal) = if self.inside { (radius + distance, -normal) } else { (radius - distance, normal) };
+        (depth > 0.).then_some((normal, depth))
+    }
+
+    /// Like [`Collider::contact`] for a particle that moved from `settled` to `pos` in this step.
+    /// A particle whose centre crossed a polyline is moved back to the side of `settled`,
+    /// instead of out on the far side where the distance alone would put it.
+    pub fn contact_along(&self, settled: Vec2, pos: Vec2, radius: f32) -> Option<(Vec2, f32)> {
+        if let Shape::Polyline(vertices) = &self.shape {
+            let rotation = Mat2::from_angle(self.rotation);
+            let local = |p: Vec2| rotation.transpose() * (p - self.position);
+            let (from, to) = (local(settled), local(pos));
+            if let Some((crossing, edge)) = first_crossing(vertices, from, to) {
+                let normal = edge.perp().normalize_or_zero();
+                let normal = if normal.dot(from - crossing) < 0. { -normal } else { normal };
+                return Some((rotation * normal, radius + normal.dot(crossing - to)));
+            }
+        }
+        self.contact(pos, radius)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.position.is_finite() && self.rotation.is_finite() && self.velocity.is_finite()
+                && self.angular_velocity.is_finite() && self.surface_speed.is_finite(),
+            "collider motion is not finite"
+        );
+        match &self.shape {
+            Shape::Circle { center, radius } => {
+                ensure!(center.is_finite() && radius.is_finite() && *radius > 0., "invalid circle {center} {radius}");
+            }
+            Shape::Polygon(vertices) => {
+                ensure!(vertices.len() >= 3, "a polygon needs at least 3 vertices, got {}", vertices.len());
+                ensure!(vertices.iter().all(|v| v.is_finite()), "polygon has non-finite vertices");
+                ensure!(signed_area(vertices) != 0., "polygon has no area");
+            }
+            Shape::Polyline(vertices) => {
+                ensure!(vertices.len() >= 2, "a polyline needs at least 2 vertices, got {}", vertices.len());
+                ensure!(vertices.iter().all(|v| v.is_finite()), "polyline has non-finite vertices");
+                ensure!(!self.inside, "a polyline has no inside");
+            }
+            Shape::Field(field) => field.validate()?,
+        }
+        Ok(())
+    }
+}
+
+/// Distance to the closest point on the segments between consecutive `vertices`, the point
+/// itself and the direction of its segment.
+fn closest_on_segments(vertices: &[Vec2], closed: bool, pos: Vec2) -> (f32, Vec2, Vec2) {
+    let n = vertices.len();
+    let segments = if closed { n } else { n - 1 };
+    let mut best = (f32::INFINITY, pos, Vec2::X);
+    for k in 0..segments {
+        let (a, b) = (vertices[k], vertices[(k + 1) % n]);
+        let ab = b - a;
+        let t = ((pos - a).dot(ab) / ab.length_squared()).clamp(0., 1.);
+        let closest = if t.is_finite() { a + ab * t } else { a };
+        let distance = pos.distance(closest);
+        if distance < best.0 {
+            best = (distance, closest, ab);
+        }
+    }
+    best
+}
+
+/// Point where the move from `from` to `to` first crosses one of the segments between
+/// consecutive `vertices`, and the direction of that segment.
+fn first_crossing(vertices: &[Vec2], from: Vec2, to: Vec2) -> Option<(Vec2, Vec2)> {
+    let step = to - from;
+    let mut best: Option<(f32, Vec2)> = None;
+    for segment in vertices.windows(2) {
+        let (a, ab) = (segment[0], segment[1] - segment[0]);
+        let denominator = step.perp_dot(ab);
+        if denominator == 0. {
+            continue;
+        }
+        let t = (a - from).perp_dot(ab) / denominator; // along the move
+        let u = (a - from).perp_dot(step) / denominator; // along the segment
+        if t > 0. && t <= 1. && (0. ..=1.).contains(&u) && best.is_none_or(|(best, _)| t < best) {
+            best = Some((t, ab));
+        }
+    }
+    best.map(|(t, edge)| (from + step * t, edge))
+}
+
+/// Even-odd test, so that concave polygons work too.
+fn contains(vertices: &[Vec2], pos: Vec2) -> bool {
+    let mut inside = false;
+    let mut j = vertices.len() - 1;
+    for (i, &a) in vertices.iter().enumerate() {
+        let b = vertices[j];
+        if (a.y > pos.y) != (b.y > pos.y) && pos.x < a.x + (pos.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Positive for counter-clockwise vertices.
+fn signed_area(vertices: &[Vec2]) -> f32 {
+    let n = vertices.len();
+    (0..n).map(|k| vertices[k].perp_dot(vertices[(k + 1) % n])).sum::<f32>() / 2.
+}
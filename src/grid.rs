@@ -0,0 +1,410 @@
+use std::ops::{Index, Range};
+
+use glam::Vec2;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+
+/// Uniform grid storing every element in a flat array sorted by cell.
+///
+/// Cells have no capacity limit: the grid is rebuilt with a counting sort, so the
+/// elements of a cell are contiguous and `offsets[c]..offsets[c + 1]` is their range.
+#[derive(Clone)]
+pub struct Grid<T>
+where
+    T: Clone + Copy + Default,
+{
+    pub width: usize,
+    pub height: usize,
+    offsets: Vec<usize>,
+    elements: Vec<T>,
+}
+
+impl<T> Index<(usize, usize)> for Grid<T>
+where
+    T: Clone + Copy + Default,
+{
+    type Output = [T];
+    fn index(&self, (i, j): (usize, usize)) -> &Self::Output {
+        let ind = i * self.height + j;
+        &self.elements[self.offsets[ind]..self.offsets[ind + 1]]
+    }
+}
+
+impl<T> Grid<T>
+where
+    T: Clone + Copy + Default,
+{
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            offsets: vec![0; width * height + 1],
+            elements: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.offsets.fill(0);
+        self.elements.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// All elements, sorted by column, then row.
+    pub fn elements(&self) -> &[T] {
+        &self.elements
+    }
+
+    /// Range of [`Grid::elements`] stored in the cell.
+    pub fn range(&self, (i, j): (usize, usize)) -> Range<usize> {
+        let ind = i * self.height + j;
+        self.offsets[ind]..self.offsets[ind + 1]
+    }
+
+    /// Replaces the contents of the grid with `(cell, value)` pairs.
+    /// Values keep their relative order inside a cell.
+    pub fn fill<I>(&mut self, items: I)
+    where
+        I: IntoIterator<Item = ((usize, usize), T)>,
+        I::IntoIter: Clone,
+    {
+        let items = items.into_iter();
+        let cell_index = |(i, j): (usize, usize)| i * self.height + j;
+
+        // count elements per cell, shifted by one so the prefix sum yields start offsets
+        self.offsets.fill(0);
+        for (cell, _) in items.clone() {
+            self.offsets[cell_index(cell) + 1] += 1;
+        }
+        for c in 1..self.offsets.len() {
+            self.offsets[c] += self.offsets[c - 1];
+        }
+
+        let total = self.offsets[self.offsets.len() - 1];
+        self.elements.clear();
+        self.elements.resize(total, T::default());
+        // `offsets[c]` is used as the insertion cursor of cell `c`...
+        for (cell, value) in items {
+            let c = cell_index(cell);
+            self.elements[self.offsets[c]] = value;
+            self.offsets[c] += 1;
+        }
+        // ...which leaves every cursor at the start of cell `c + 1`, so shift them back
+        for c in (1..self.offsets.len()).rev() {
+            self.offsets[c] = self.offsets[c - 1];
+        }
+        self.offsets[0] = 0;
+    }
+}
+
+/// Cells of one level of the hierarchical broad phase.
+///
+/// A particle belongs to the finest level whose cells are at least as large as its
+/// diameter, so any overlapping particle of the same or a finer level lies in the
+/// 3x3 neighbourhood of its cell. Besides its own particles a level also stores the
+/// particles of all finer levels, which only have to be checked against its own ones.
+///
+/// Every cell is split into an "own" and a "finer" sub-cell stored next to each other,
+/// so the elements are sorted by column, then row, with own particles first. The
+/// collision pass relies on this order to hand out contiguous runs of columns to threads.
+pub trait LevelCells: Sync {
+    /// Particle indices in storage order.
+    fn elements(&self) -> &[usize];
+
+    /// Range of [`LevelCells::elements`] holding own particles of the cell.
+    fn own(&self, cell: (i32, i32)) -> Range<usize>;
+
+    /// Range of [`LevelCells::elements`] holding own and finer particles of the cell.
+    fn cell(&self, cell: (i32, i32)) -> Range<usize>;
+
+    /// Index of the first element in column `col` or in any column after it.
+    fn column_start(&self, col: i32) -> usize;
+
+    /// Sorted columns that may contain particles.
+    fn columns(&self) -> Vec<i32>;
+
+    /// Calls `f` for every cell of the columns that may contain particles.
+    fn for_each_cell(&self, cols: Range<i32>, f: impl FnMut((i32, i32)));
+}
+
+/// Level of the dense [`Grid`] broad phase.
+#[derive(Clone)]
+pub struct GridLevel {
+    pub bounds: (Vec2, Vec2), // area covered by the inner cells
+    pub cell_size: f32,
+    pub width: usize,
+    pub height: usize,
+    cells: Grid<usize>, // own and finer sub-cells are rows `2 * row` and `2 * row + 1`
+}
+
+impl GridLevel {
+    pub fn new(bounds: (Vec2, Vec2), cell_size: f32) -> Self {
+        let width: usize = ((bounds.1.x - bounds.0.x) / cell_size) as usize + 3;
+        let height: usize = ((bounds.1.y - bounds.0.y) / cell_size) as usize + 3;
+        Self {
+            bounds,
+            cell_size,
+            width,
+            height,
+            cells: Grid::new(width, 2 * height),
+        }
+    }
+
+    // border cells are kept empty so that neighbours of any inner cell are always valid
+    pub fn get_cell(&self, origin: Vec2, pos: Vec2) -> (i32, i32) {
+        (
+            (((pos.x - origin.x) / self.cell_size).max(0.) as usize + 1).min(self.width - 2) as i32,
+            (((pos.y - origin.y) / self.cell_size).max(0.) as usize + 1).min(self.height - 2) as i32,
+        )
+    }
+
+    /// Replaces the contents of the level with `(cell, particle, is_own)` triples.
+    pub fn fill<I>(&mut self, items: I)
+    where
+        I: IntoIterator<Item = ((i32, i32), usize, bool)>,
+        I::IntoIter: Clone,
+    {
+        self.cells.fill(items.into_iter().map(|((col, row), i, own)| {
+            ((col as usize, 2 * row as usize + !own as usize), i)
+        }));
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    fn contains(&self, (col, row): (i32, i32)) -> bool {
+        (0..self.width as i32).contains(&col) && (0..self.height as i32).contains(&row)
+    }
+}
+
+impl LevelCells for GridLevel {
+    fn elements(&self) -> &[usize] {
+        self.cells.elements()
+    }
+
+    fn own(&self, cell: (i32, i32)) -> Range<usize> {
+        if !self.contains(cell) {
+            return 0..0;
+        }
+        self.cells.range((cell.0 as usize, 2 * cell.1 as usize))
+    }
+
+    fn cell(&self, cell: (i32, i32)) -> Range<usize> {
+        if !self.contains(cell) {
+            return 0..0;
+        }
+        let own = self.cells.range((cell.0 as usize, 2 * cell.1 as usize));
+        let finer = self.cells.range((cell.0 as usize, 2 * cell.1 as usize + 1));
+        own.start..finer.end
+    }
+
+    fn column_start(&self, col: i32) -> usize {
+        let col = col.clamp(0, self.width as i32);
+        if col == self.width as i32 {
+            return self.cells.len();
+        }
+        self.cells.range((col as usize, 0)).start
+    }
+
+    fn columns(&self) -> Vec<i32> {
+        (1..self.width as i32 - 1).collect()
+    }
+
+    fn for_each_cell(&self, cols: Range<i32>, mut f: impl FnMut((i32, i32))) {
+        for col in cols.start.max(1)..cols.end.min(self.width as i32 - 1) {
+            for row in 1..self.height as i32 - 1 {
+                f((col, row));
+            }
+        }
+    }
+}
+
+/// Sparse grid over unbounded space, only occupied cells take memory.
+///
+/// Like [`Grid`] the elements are stored in one flat array sorted by cell, the hash map
+/// translates integer cell coordinates into ranges of that array.
+#[derive(Clone, Default)]
+pub struct SpatialHash<T>
+where
+    T: Clone + Copy + Default,
+{
+    cells: FxHashMap<(i32, i32), Range<usize>>,
+    keys: Vec<(i32, i32)>, // occupied cells sorted by column, then row
+    elements: Vec<T>,
+}
+
+impl<T> Index<(i32, i32)> for SpatialHash<T>
+where
+    T: Clone + Copy + Default,
+{
+    type Output = [T];
+    fn index(&self, key: (i32, i32)) -> &Self::Output {
+        match self.cells.get(&key) {
+            Some(range) => &self.elements[range.clone()],
+            None => &[],
+        }
+    }
+}
+
+impl<T> SpatialHash<T>
+where
+    T: Clone + Copy + Default + Send,
+{
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.keys.clear();
+        self.elements.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Occupied cells sorted by column, then row.
+    pub fn keys(&self) -> &[(i32, i32)] {
+        &self.keys
+    }
+
+    /// All elements, sorted by column, then row.
+    pub fn elements(&self) -> &[T] {
+        &self.elements
+    }
+
+    /// Range of [`SpatialHash::elements`] stored in the cell, empty if it is not occupied.
+    pub fn range(&self, key: (i32, i32)) -> Range<usize> {
+        self.cells.get(&key).cloned().unwrap_or_default()
+    }
+
+    /// Replaces the contents of the hash with `(cell, value)` pairs.
+    /// Values keep their relative order inside a cell.
+    pub fn fill<I>(&mut self, items: I)
+    where
+        I: IntoIterator<Item = ((i32, i32), T)>,
+    {
+        let mut items: Vec<((i32, i32), T)> = items.into_iter().collect();
+        items.par_sort_by_key(|&(key, _)| key);
+
+        self.clear();
+        self.elements.reserve(items.len());
+        for (key, value) in items {
+            if self.keys.last() != Some(&key) {
+                let start = self.elements.len();
+                self.keys.push(key);
+                self.cells.insert(key, start..start);
+            }
+            self.elements.push(value);
+            self.cells.get_mut(&key).unwrap().end += 1;
+        }
+    }
+}
+
+/// Largest cell coordinate of a [`HashLevel`] on either axis.
+pub const MAX_HASH_CELL: i32 = 1 << 28;
+
+/// Level of the [`SpatialHash`] broad phase.
+#[derive(Clone)]
+pub struct HashLevel {
+    pub cell_size: f32,
+    cells: SpatialHash<usize>, // own and finer sub-cells are rows `2 * row` and `2 * row + 1`
+}
+
+impl HashLevel {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: SpatialHash::default(),
+        }
+    }
+
+    /// Cell of `pos`. Coordinates are clamped to [`MAX_HASH_CELL`], so that sub-rows and
+    /// neighbours of far away cells don't overflow, such particles share the outermost cells.
+    pub fn get_cell(&self, pos: Vec2) -> (i32, i32) {
+        let max = Vec2::splat(MAX_HASH_CELL as f32);
+        let cell = (pos / self.cell_size).floor().clamp(-max, max);
+        (cell.x as i32, cell.y as i32)
+    }
+
+    /// Replaces the contents of the level with `(cell, particle, is_own)` triples.
+    pub fn fill<I>(&mut self, items: I)
+    where
+        I: IntoIterator<Item = ((i32, i32), usize, bool)>,
+    {
+        self.cells.fill(items.into_iter().map(|((col, row), i, own)| {
+            ((col, 2 * row + !own as i32), i)
+        }));
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    // index of the first occupied sub-cell at or after `key`
+    fn first_key(&self, key: (i32, i32)) -> usize {
+        self.cells.keys().partition_point(|&k| k < key)
+    }
+}
+
+impl LevelCells for HashLevel {
+    fn elements(&self) -> &[usize] {
+        self.cells.elements()
+    }
+
+    fn own(&self, (col, row): (i32, i32)) -> Range<usize> {
+        self.cells.range((col, 2 * row))
+    }
+
+    fn cell(&self, (col, row): (i32, i32)) -> Range<usize> {
+        let own = self.cells.range((col, 2 * row));
+        let finer = self.cells.range((col, 2 * row + 1));
+        match (own.is_empty(), finer.is_empty()) {
+            (_, true) => own,
+            (true, false) => finer,
+            (false, false) => own.start..finer.end,
+        }
+    }
+
+    fn column_start(&self, col: i32) -> usize {
+        match self.cells.keys().get(self.first_key((col, i32::MIN))) {
+            Some(&key) => self.cells.range(key).start,
+            None => self.cells.len(),
+        }
+    }
+
+    fn columns(&self) -> Vec<i32> {
+        let mut columns: Vec<i32> = self.cells.keys().iter().map(|k| k.0).collect();
+        columns.dedup();
+        columns
+    }
+
+    fn for_each_cell(&self, cols: Range<i32>, mut f: impl FnMut((i32, i32))) {
+        let keys = self.cells.keys();
+        let (start, end) = (self.first_key((cols.start, i32::MIN)), self.first_key((cols.end, i32::MIN)));
+        let mut last = None;
+        for &(col, sub_row) in &keys[start..end] {
+            let cell = (col, sub_row.div_euclid(2));
+            if last != Some(cell) {
+                f(cell);
+                last = Some(cell);
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Generational particle handles.
+//!
+//! Indices into `Simulation::particles` change whenever particles are removed or
+//! reordered. A [`ParticleId`] keeps referring to the same particle until it is removed,
+//! after which it no longer resolves, even if its slot is reused for a new particle.
+
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ParticleId {
+    pub slot: u32,
+    pub generation: u32,
+}
+
+const FREE: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Slot {
+    generation: u32,
+    index: u32, // current index of the particle, `FREE` if the slot is unused
+}
+
+/// Maps particle ids to indices and back.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ParticleIds {
+    ids: Vec<ParticleId>, // id of the particle at each index
+    slots: Vec<Slot>,
+    free: Vec<u32>, // unused slots
+}
+
+impl ParticleIds {
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Id of the particle at `index`.
+    pub fn id(&self, index: usize) -> Option<ParticleId> {
+        self.ids.get(index).copied()
+    }
+
+    /// Current index of the particle, `None` if it was removed.
+    pub fn index(&self, id: ParticleId) -> Option<usize> {
+        let slot = self.slots.get(id.slot as usize)?;
+        (slot.generation == id.generation && slot.index != FREE).then_some(slot.index as usize)
+    }
+
+    /// Gives ids to particles appended at the end or frees those of particles truncated away,
+    /// so that there is one id per particle.
+    pub fn resize(&mut self, len: usize) {
+        while self.ids.len() > len {
+            let id = self.ids.pop().unwrap();
+            self.release(id);
+        }
+        while self.ids.len() < len {
+            let index = self.ids.len() as u32;
+            let slot = match self.free.pop() {
+                Some(slot) => {
+                    self.slots[slot as usize].index = index;
+                    slot
+                }
+                None => {
+                    self.slots.push(Slot { generation: 0, index });
+                    self.slots.len() as u32 - 1
+                }
+            };
+            let generation = self.slots[slot as usize].generation;
+            self.ids.push(ParticleId { slot, generation });
+        }
+    }
+
+    /// Moves the particle at every old index `i` to `map[i]`, releasing the ids of particles
+    /// that map to `None`. `len` is the new number of particles.
+    pub fn remap(&mut self, map: &[Option<usize>], len: usize) {
+        let mut ids = vec![ParticleId { slot: FREE, generation: 0 }; len];
+        let old = std::mem::take(&mut self.ids);
+        for (&id, &new) in old.iter().zip(map.iter()) {
+            match new {
+                Some(new) => {
+                    ids[new] = id;
+                    self.slots[id.slot as usize].index = new as u32;
+                }
+                None => self.release(id),
+            }
+        }
+        self.ids = ids;
+    }
+
+    fn release(&mut self, id: ParticleId) {
+        let slot = &mut self.slots[id.slot as usize];
+        slot.generation = slot.generation.wrapping_add(1);
+        slot.index = FREE;
+        self.free.push(id.slot);
+    }
+
+    /// Checks that the ids are consistent and cover `len` particles.
+    pub fn validate(&self, len: usize) -> Result<()> {
+        ensure!(self.ids.len() == len, "{} particle ids for {len} particles", self.ids.len());
+        for (index, &id) in self.ids.iter().enumerate() {
+            ensure!(self.index(id) == Some(index), "particle id {id:?} does not point at particle {index}");
+        }
+        let used = self.slots.iter().filter(|slot| slot.index != FREE).count();
+        ensure!(
+            used == len && used + self.free.len() == self.slots.len(),
+            "particle id slots are inconsistent"
+        );
+        Ok(())
+    }
+}
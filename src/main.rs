@@ -1,24 +1,23 @@
-mod particle;
 mod scene;
-mod solver;
 mod texture;
 
-use iced_core::SmolStr;
 use scene::{Scene, MAX_FOV};
 
 use iced::executor;
 use iced::time;
 use iced::time::Instant;
-use iced::widget::{column, row, shader, slider, text};
+use iced::widget::{column, pick_list, row, shader, slider, text};
 use iced::Command;
 use iced::Theme;
 use iced::{event, keyboard, Application};
 use iced::{Alignment, Element, Length, Subscription};
 
-use glam::{vec2, Vec2};
+use glam::vec2;
+use verlet_integration::recording::Recording;
+use verlet_integration::MaterialRegistry;
+use verlet_integration::solver::{self, ConstraintOrder};
 use verlet_integration::CustomApplication;
 
-const SUB_TICKS: usize = 8;
 fn main() -> iced::Result {
     tracing_subscriber::fmt::init();
 
@@ -30,9 +29,15 @@ fn main() -> iced::Result {
     <Simulation as CustomApplication>::run(iced::Settings::default())
 }
 
+/// Where `R` saves the recording and `P` loads it from.
+const RECORDING_PATH: &str = "recording.vrec";
+/// Materials to use instead of the built-in ones, if the file exists.
+const MATERIALS_PATH: &str = "materials.json";
+
 struct Simulation {
     start: Instant,
     scene: Scene,
+    playing: bool, // whether the replay advances on its own
 }
 
 impl CustomApplication for Simulation {}
@@ -48,13 +53,23 @@ impl Application for Simulation {
     }
 
     fn new(_flags: Self::Flags) -> (Self, Command<Self::Message>) {
+        let materials = if std::path::Path::new(MATERIALS_PATH).exists() {
+            MaterialRegistry::load(MATERIALS_PATH).unwrap_or_else(|err| {
+                eprintln!("using the built-in materials: {err:#}");
+                MaterialRegistry::default()
+            })
+        } else {
+            MaterialRegistry::default()
+        };
         (
             Self {
                 start: Instant::now(),
                 scene: Scene::new(
                     10,
                     solver::Constraint::Box(vec2(-60., -10.), vec2(60., 40.)),
+                    materials,
                 ),
+                playing: true,
             },
             Command::none(),
         )
@@ -74,11 +89,65 @@ impl Application for Simulation {
             Message::CameraYUpdated(y) => {
                 self.scene.camera.pos.y = y;
             }
+            Message::GravityXChanged(x) => {
+                let mut parameters = self.scene.simulation.parameters;
+                parameters.gravity.x = x;
+                self.scene.set_parameters(parameters);
+            }
+            Message::GravityYChanged(y) => {
+                let mut parameters = self.scene.simulation.parameters;
+                parameters.gravity.y = y;
+                self.scene.set_parameters(parameters);
+            }
+            Message::DampingChanged(damping) => {
+                let mut parameters = self.scene.simulation.parameters;
+                parameters.damping = damping;
+                self.scene.set_parameters(parameters);
+            }
+            Message::SubTicksChanged(sub_ticks) => {
+                let mut parameters = self.scene.simulation.parameters;
+                parameters.sub_ticks = sub_ticks as usize;
+                self.scene.set_parameters(parameters);
+            }
+            Message::FrameDtChanged(frame_dt) => {
+                let mut parameters = self.scene.simulation.parameters;
+                parameters.frame_dt = frame_dt;
+                self.scene.set_parameters(parameters);
+            }
+            Message::IterationsChanged(iterations) => {
+                let mut parameters = self.scene.simulation.parameters;
+                parameters.iterations = iterations as usize;
+                self.scene.set_parameters(parameters);
+            }
+            Message::ConstraintOrderSelected(order) => {
+                let mut parameters = self.scene.simulation.parameters;
+                parameters.constraint_order = order;
+                self.scene.set_parameters(parameters);
+            }
+            Message::MaterialSelected(name) => {
+                if let Some(id) = self.scene.simulation.materials.id(&name) {
+                    self.scene.set_spawn_material(id);
+                }
+            }
             Message::Tick(_time) => {
                 let time = Instant::now();
-                let dt = 0.08/SUB_TICKS as f32;
-                for _ in 0..SUB_TICKS {
-                    self.scene.update(dt);
+                let parameters = self.scene.current().parameters;
+                match &mut self.scene.player {
+                    Some(player) if self.playing => {
+                        for _ in 0..parameters.sub_ticks {
+                            if let Err(err) = player.step_forward() {
+                                eprintln!("replay paused: {err:#}");
+                                self.playing = false;
+                                break;
+                            }
+                        }
+                    }
+                    Some(_) => {}
+                    None => {
+                        for _ in 0..parameters.sub_ticks {
+                            self.scene.update(parameters.dt());
+                        }
+                    }
                 }
                 //println!("{}", (Instant::now() - time).as_nanos() as f32 / 1000000.);
             }
@@ -89,26 +158,62 @@ impl Application for Simulation {
                     modifiers: _,
                     text: _,
                 }) => {
-                    self.scene.change_number(self.scene.simulation.particles.len() + 100);
+                    if self.scene.player.is_some() {
+                        self.playing = !self.playing;
+                    } else {
+                        self.scene.change_number(self.scene.simulation.particles.len() + 100);
+                    }
                 }
+                event::Event::Keyboard(keyboard::Event::KeyPressed {
+                    key: keyboard::Key::Character(c),
+                    location: _,
+                    modifiers: _,
+                    text: _,
+                }) => match c.as_str() {
+                    "r" => self.toggle_recording(),
+                    "p" => self.toggle_replay(),
+                    _ => {}
+                },
                 _ => {}
             },
+            Message::ReplayScrubbed(step) => {
+                if let Some(player) = &mut self.scene.player {
+                    self.playing = false;
+                    if let Err(err) = player.seek(step as usize) {
+                        eprintln!("cannot seek to step {step}: {err:#}");
+                    }
+                }
+            }
         }
 
         Command::none()
     }
 
     fn view(&self) -> Element<'_, Message> {
-        let number_str = self.scene.simulation.particles.len().to_string();
-        let number_controls = row![control(
-            &number_str,
-            slider(
-                1..=solver::MAX,
-                self.scene.simulation.particles.len() as u32,
-                |n| Message::ParticlesNumberChanged(n as usize)
-            )
-            .width(3000)
-        ),]
+        let number_controls = match &self.scene.player {
+            Some(player) => {
+                let steps = player.recording().steps() as u32;
+                row![control(
+                    &format!("replay {}/{steps}", player.step()),
+                    slider(0..=steps, player.step() as u32, Message::ReplayScrubbed).width(3000)
+                ),]
+            }
+            None => {
+                let mut number_str = self.scene.simulation.particles.len().to_string();
+                if let Some(recorder) = &self.scene.recorder {
+                    number_str += &format!(" (recording {})", recorder.steps());
+                }
+                row![control(
+                    &number_str,
+                    slider(
+                        1..=solver::MAX,
+                        self.scene.simulation.particles.len() as u32,
+                        |n| Message::ParticlesNumberChanged(n as usize)
+                    )
+                    .width(3000)
+                ),]
+            }
+        }
         .spacing(40);
 
         let fov_controls = row![control(
@@ -143,7 +248,70 @@ impl Application for Simulation {
         .spacing(40);
 
         let camera_controls = row![fov_controls, x_controls, y_controls].spacing(10);
-        let controls = column![number_controls, camera_controls]
+
+        let parameters = self.scene.current().parameters;
+        let gravity_controls = row![
+            control(
+                &format!("gravity X {:.2}", parameters.gravity.x),
+                slider(-5. ..=5., parameters.gravity.x, Message::GravityXChanged)
+                    .step(0.05)
+                    .width(200)
+            ),
+            control(
+                &format!("Y {:.2}", parameters.gravity.y),
+                slider(-5. ..=5., parameters.gravity.y, Message::GravityYChanged)
+                    .step(0.05)
+                    .width(200)
+            ),
+        ]
+        .spacing(40);
+        let damping_controls = row![control(
+            &format!("damping {:.1}", parameters.damping),
+            slider(0. ..=30., parameters.damping, Message::DampingChanged)
+                .step(0.1)
+                .width(200)
+        ),]
+        .spacing(40);
+        let time_controls = row![
+            control(
+                &format!("substeps {}", parameters.sub_ticks),
+                slider(1..=32, parameters.sub_ticks as u32, Message::SubTicksChanged).width(200)
+            ),
+            control(
+                &format!("dt {:.3}", parameters.frame_dt),
+                slider(0.005..=0.2, parameters.frame_dt, Message::FrameDtChanged)
+                    .step(0.005)
+                    .width(200)
+            ),
+        ]
+        .spacing(40);
+        let constraint_controls = row![
+            control(
+                &format!("iterations {}", parameters.iterations),
+                slider(1..=20, parameters.iterations as u32, Message::IterationsChanged).width(200)
+            ),
+            pick_list(
+                &ConstraintOrder::ALL[..],
+                Some(parameters.constraint_order),
+                Message::ConstraintOrderSelected
+            ),
+        ]
+        .spacing(40);
+        let simulation = self.scene.current();
+        let material_names: Vec<String> = simulation.materials.visible().map(|(_, m)| m.name.clone()).collect();
+        let material_controls = row![control(
+            "material",
+            pick_list(
+                material_names,
+                simulation.materials.get(simulation.spawn_material).map(|m| m.name.clone()),
+                Message::MaterialSelected
+            )
+        ),]
+        .spacing(40);
+        let parameter_controls =
+            row![material_controls, gravity_controls, damping_controls, time_controls, constraint_controls].spacing(10);
+
+        let controls = column![number_controls, camera_controls, parameter_controls]
             .spacing(10)
             .padding(20)
             .align_items(Alignment::Center);
@@ -167,14 +335,57 @@ impl Application for Simulation {
     }
 }
 
+impl Simulation {
+    /// Starts recording the live simulation, or stops and saves the running recording.
+    fn toggle_recording(&mut self) {
+        if self.scene.player.is_some() {
+            return;
+        }
+        let result = match self.scene.stop_recording() {
+            Some(recording) => recording.save(RECORDING_PATH),
+            None => self.scene.start_recording(),
+        };
+        if let Err(err) = result {
+            eprintln!("recording failed: {err:#}");
+        }
+    }
+
+    /// Enters replay mode with the last saved recording, or goes back to the live simulation.
+    fn toggle_replay(&mut self) {
+        if self.scene.player.is_some() {
+            self.scene.stop_replay();
+            return;
+        }
+        if let Some(recording) = self.scene.stop_recording() {
+            if let Err(err) = recording.save(RECORDING_PATH) {
+                eprintln!("recording failed: {err:#}");
+            }
+        }
+        let result = Recording::load(RECORDING_PATH).and_then(|recording| self.scene.start_replay(recording));
+        match result {
+            Ok(()) => self.playing = true,
+            Err(err) => eprintln!("cannot replay {RECORDING_PATH}: {err:#}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 enum Message {
     ParticlesNumberChanged(usize),
     CameraFovChanged(f32),
     CameraXUpdated(f32),
     CameraYUpdated(f32),
+    GravityXChanged(f32),
+    GravityYChanged(f32),
+    DampingChanged(f32),
+    SubTicksChanged(u32),
+    FrameDtChanged(f32),
+    MaterialSelected(String),
+    IterationsChanged(u32),
+    ConstraintOrderSelected(ConstraintOrder),
     Event(iced::Event),
     Tick(Instant),
+    ReplayScrubbed(u32),
 }
 
 fn control<'a>(label: &str, control: impl Into<Element<'a, Message>>) -> Element<'a, Message> {
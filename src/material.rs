@@ -0,0 +1,167 @@
+//! Data-driven particle materials.
+//!
+//! A [`MaterialRegistry`] is a list of named [`Material`]s, usually loaded from a JSON
+//! file (see `materials.json` for the built-in set). Particles store the id of their
+//! material, the index into the registry, along with a copy of its physical properties so
+//! the solver does not need to look them up.
+
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::particle::Particle;
+
+pub type MaterialId = u32;
+
+/// The built-in materials, also used for scenes saved without a registry.
+const DEFAULT_MATERIALS: &str = include_str!("../materials.json");
+
+/// Number of particle textures, the non-empty lines of `textures/textures.txt`.
+pub const TEXTURE_COUNT: u32 = count_lines(include_str!("../textures/textures.txt"));
+
+const fn count_lines(text: &str) -> u32 {
+    let bytes = text.as_bytes();
+    let (mut count, mut in_line, mut i) = (0, false, 0);
+    while i < bytes.len() {
+        if bytes[i] == b'\n' {
+            in_line = false;
+        } else if !in_line && !bytes[i].is_ascii_whitespace() {
+            in_line = true;
+            count += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MaterialFlag {
+    /// Not offered in material lists of the viewer.
+    Hidden,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Material {
+    pub name: String,
+    pub density: f32, // mass per unit of area
+    pub radius: f32,
+    #[serde(default)]
+    pub friction: f32,
+    #[serde(default)]
+    pub restitution: f32,
+    #[serde(default)]
+    pub cohesion: f32, // share of the gap closed per step between nearly touching particles
+    #[serde(default)]
+    pub texture: u32, // index into textures.txt
+    #[serde(default = "white")]
+    pub colour: [f32; 4], // RGBA tint of the texture
+    #[serde(default)]
+    pub flags: Vec<MaterialFlag>,
+}
+
+fn white() -> [f32; 4] {
+    [1.; 4]
+}
+
+impl Material {
+    pub fn mass(&self) -> f32 {
+        self.density * PI * self.radius * self.radius
+    }
+
+    pub fn has_flag(&self, flag: MaterialFlag) -> bool {
+        self.flags.contains(&flag)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaterialRegistry {
+    pub materials: Vec<Material>,
+}
+
+impl Default for MaterialRegistry {
+    fn default() -> Self {
+        MaterialRegistry::from_json(DEFAULT_MATERIALS).expect("built-in materials are valid")
+    }
+}
+
+impl MaterialRegistry {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).with_context(|| format!("cannot open {}", path.display()))?;
+        MaterialRegistry::from_json(&json).with_context(|| format!("cannot load materials {}", path.display()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let registry: MaterialRegistry = serde_json::from_str(json)?;
+        registry.validate()?;
+        Ok(registry)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(!self.materials.is_empty(), "no materials defined");
+        for (id, m) in self.materials.iter().enumerate() {
+            if self.materials[..id].iter().any(|other| other.name == m.name) {
+                bail!("material {:?} is defined twice", m.name);
+            }
+            ensure!(
+                [m.density, m.radius, m.friction, m.restitution, m.cohesion].iter().all(|v| v.is_finite())
+                    && m.density > 0. && m.radius > 0. && m.friction >= 0. && m.restitution >= 0. && m.cohesion >= 0.,
+                "material {:?} needs a finite positive density and radius and non-negative coefficients",
+                m.name
+            );
+            ensure!(
+                m.texture < TEXTURE_COUNT,
+                "material {:?} uses the texture {}, only {TEXTURE_COUNT} textures exist",
+                m.name,
+                m.texture
+            );
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    pub fn get(&self, id: MaterialId) -> Option<&Material> {
+        self.materials.get(id as usize)
+    }
+
+    /// Id of the material with the given name.
+    pub fn id(&self, name: &str) -> Option<MaterialId> {
+        self.materials.iter().position(|m| m.name == name).map(|id| id as MaterialId)
+    }
+
+    /// Materials that are not [`MaterialFlag::Hidden`], with their ids.
+    pub fn visible(&self) -> impl Iterator<Item = (MaterialId, &Material)> {
+        self.materials
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| !m.has_flag(MaterialFlag::Hidden))
+            .map(|(id, m)| (id as MaterialId, m))
+    }
+
+    /// A particle of material `id` at rest at `pos`.
+    pub fn particle(&self, id: MaterialId, pos: Vec2) -> Particle {
+        let m = &self.materials[id as usize];
+        Particle {
+            radius: m.radius,
+            mass: m.mass(),
+            friction: m.friction,
+            restitution: m.restitution,
+            cohesion: m.cohesion,
+            material: id,
+            texture: m.texture,
+            ..Particle::null()
+        }
+        .place(pos)
+    }
+}
@@ -0,0 +1,63 @@
+//! Helpers for mutating disjoint parts of a slice from several threads without `unsafe`.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Splits `data` into consecutive disjoint slices `data[bounds[k]..bounds[k + 1]]`.
+/// `bounds` must be ascending, start at 0 and end at `data.len()`.
+pub fn split_at_bounds<'a, T>(mut data: &'a mut [T], bounds: &[usize]) -> Vec<&'a mut [T]> {
+    debug_assert_eq!(bounds.first(), Some(&0));
+    debug_assert_eq!(bounds.last(), Some(&data.len()));
+    let mut slices = Vec::with_capacity(bounds.len().saturating_sub(1));
+    for pair in bounds.windows(2) {
+        let (head, tail) = std::mem::take(&mut data).split_at_mut(pair[1] - pair[0]);
+        slices.push(head);
+        data = tail;
+    }
+    slices
+}
+
+/// Mutable references to two different elements of a slice.
+pub fn pair_mut<T>(data: &mut [T], a: usize, b: usize) -> (&mut T, &mut T) {
+    assert_ne!(a, b, "pair_mut requires two different indices");
+    if a < b {
+        let (head, tail) = data.split_at_mut(b);
+        (&mut head[a], &mut tail[0])
+    } else {
+        let (head, tail) = data.split_at_mut(a);
+        (&mut tail[0], &mut head[b])
+    }
+}
+
+/// Records which task touched each element during a parallel pass and panics as soon
+/// as two different tasks touch the same element. Meant for debug builds.
+pub struct AccessChecker {
+    owners: Vec<AtomicUsize>, // task + 1, 0 if untouched
+}
+
+impl AccessChecker {
+    pub fn new(len: usize) -> Self {
+        Self {
+            owners: (0..len).map(|_| AtomicUsize::new(0)).collect(),
+        }
+    }
+
+    /// Marks `index` as touched by `task`.
+    pub fn claim(&self, index: usize, task: usize) {
+        let owner = match self.owners[index].compare_exchange(0, task + 1, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return,
+            Err(owner) => owner,
+        };
+        assert!(
+            owner == task + 1,
+            "element {index} was touched by tasks {} and {task} in the same pass",
+            owner - 1
+        );
+    }
+
+    /// Forgets all claims, to be called between passes.
+    pub fn reset(&mut self) {
+        for owner in self.owners.iter_mut() {
+            *owner.get_mut() = 0;
+        }
+    }
+}
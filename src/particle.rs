@@ -1,25 +1,36 @@
-use glam::{vec2, Vec2};
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
 
-use crate::solver::{self, Constraint};
+use crate::collider::Collider;
+use crate::material::MaterialId;
+use crate::solver::{self, Constraint, Walls};
 
-pub const SAND : Particle = Particle {
-    radius: solver::PARTICLE_SIZE,
-    mass: 1.,
-    texture: 0,
-    ..Particle::null()
-};
-
-pub const METAL : Particle = Particle {
-    radius: solver::PARTICLE_SIZE,
-    mass: 10.,
-    texture: 1,
-    ..Particle::null()
-};
+/// How a particle is moved.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParticleMode {
+    /// Integrated and pushed by collisions, connections and walls.
+    #[default]
+    Dynamic,
+    /// Never moves, behaves as if it had infinite mass.
+    Pinned,
+    /// Moved only by user code through [`Particle::drive`], pushes others but is not pushed.
+    Kinematic,
+}
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Particle {
     pub radius: f32,
     pub mass: f32,
+    #[serde(default)]
+    pub friction: f32, // Coulomb coefficient, 0 is frictionless
+    #[serde(default)]
+    pub restitution: f32, // share of the normal velocity kept after a hit, 0 is fully inelastic
+    #[serde(default)]
+    pub cohesion: f32, // share of the gap closed per step to nearly touching particles
+    #[serde(default)]
+    pub material: MaterialId, // the other properties are copied from this material
+    #[serde(default)]
+    pub mode: ParticleMode,
     pub pos: glam::Vec2,
     pub pos_old: glam::Vec2,
     pub acc: glam::Vec2,
@@ -33,13 +44,15 @@ impl Default for Particle {
 }
 
 impl Particle {
-    const GRAVITY : Vec2 = vec2(0., -1.);
-    const SLOWDOWN : f32 = 10.;
-
     pub const fn null() -> Self {
         Self {
             radius: solver::PARTICLE_SIZE,
             mass: 1.,
+            friction: 0.,
+            restitution: 0.,
+            cohesion: 0.,
+            material: 0,
+            mode: ParticleMode::Dynamic,
             texture: 0,
             pos: glam::Vec2::ZERO,
             pos_old: glam::Vec2::ZERO,
@@ -58,6 +71,11 @@ impl Particle {
         Self {
             radius,
             mass,
+            friction: 0.,
+            restitution: 0.,
+            cohesion: 0.,
+            material: 0,
+            mode: ParticleMode::Dynamic,
             pos,
             pos_old: pos,
             acc: glam::Vec2::ZERO,
@@ -65,15 +83,41 @@ impl Particle {
         }
     }
 
-    pub fn update(&mut self, dt: f32) {
+    /// Share of a correction between two particles that this one takes: 1 for dynamic
+    /// particles and 0 for pinned and kinematic ones.
+    pub fn mobility(&self) -> f32 {
+        match self.mode {
+            ParticleMode::Dynamic => 1.,
+            ParticleMode::Pinned | ParticleMode::Kinematic => 0.,
+        }
+    }
+
+    /// Zero for particles that are not moved by the solver.
+    pub fn inverse_mass(&self) -> f32 {
+        self.mobility() / self.mass
+    }
+
+    /// Moves a kinematic particle to `pos`. Its velocity until the next update is the
+    /// displacement, so it drags touching particles along through friction.
+    pub fn drive(&mut self, pos: Vec2) {
+        self.pos_old = self.pos;
+        self.pos = pos;
+    }
+
+    pub fn update(&mut self, dt: f32, damping: f32) {
+        if self.mode != ParticleMode::Dynamic {
+            self.pos_old = self.pos;
+            self.acc = Vec2::ZERO;
+            return;
+        }
         let vel = self.pos - self.pos_old;
-        let new_pos = self.pos + vel + (self.acc - vel*Particle::SLOWDOWN)*dt*dt;
+        let new_pos = self.pos + vel + (self.acc - vel*damping)*dt*dt;
         self.pos_old = self.pos;
         self.set_position(new_pos, false);
     }
 
-    pub fn apply_gravity(&mut self) {
-        self.accelerate(Particle::GRAVITY);
+    pub fn apply_gravity(&mut self, gravity: Vec2) {
+        self.accelerate(gravity);
     }
 
     pub fn accelerate(&mut self, acceleration: Vec2) {
@@ -85,23 +129,119 @@ impl Particle {
         self.acc = if keep_acc {self.acc} else {Vec2::ZERO};
     }
 
-    pub fn apply_constraint(&mut self, constraint: Constraint) {
+    /// Moves the particle back to `pos` inside a wall, reflecting the velocity into the wall
+    /// by `restitution` and slowing down the motion along the wall by `friction`.
+    fn hit_wall(&mut self, pos: Vec2) {
+        self.hit_moving_wall(pos, Vec2::ZERO);
+    }
+
+    /// Same as `hit_wall` for a wall that moves by `wall_vel` per step, the particle keeps
+    /// the wall's velocity and only its velocity relative to the wall is reflected and slowed.
+    fn hit_moving_wall(&mut self, pos: Vec2, wall_vel: Vec2) {
+        let normal = (pos - self.pos).normalize_or_zero();
+        let vel = self.pos - self.pos_old - wall_vel;
+        let vn = vel.dot(normal).min(0.);
+        let vt = vel - normal * vel.dot(normal);
+        let vt = vt * friction_scale(vt, self.friction * (1. + self.restitution) * -vn);
+        let new_vel = vt + normal * (vel.dot(normal) - (1. + self.restitution) * vn);
+        self.pos_old = pos - new_vel - wall_vel;
+        self.set_position(pos, false);
+    }
+
+    /// Keeps the particle inside the constraint. `settled` is where the particle was at the
+    /// start of the step, the walls acted on it last there, so it tells which side of a wall
+    /// of a container the particle belongs to.
+    pub fn apply_constraint(&mut self, constraint: Constraint, settled: Vec2) {
+        if self.mode != ParticleMode::Dynamic {
+            return;
+        }
         match constraint {
-            Constraint::Cup(bl, tr) => {
-                let new_x = self.pos.x.max(bl.x + self.radius).min(tr.x - self.radius);
-                let new_y = self.pos.y.max(bl.y + self.radius);
-                if (new_x, new_y) != (self.pos.x, self.pos.y) {
-                    self.set_position(vec2(new_x, new_y), false);
-                }
-            },
-            Constraint::Box(bl, tr) => {
-                let new_x = self.pos.x.max(bl.x + self.radius).min(tr.x - self.radius);
-                let new_y = self.pos.y.max(bl.y + self.radius).min(tr.y - self.radius);
-                if (new_x, new_y) != (self.pos.x, self.pos.y) {
-                    self.set_position(vec2(new_x, new_y), false);
-                }
+            Constraint::Box(bl, tr) => self.keep_inside(bl, tr, Walls::ALL),
+            Constraint::Container(bl, tr, walls) => self.keep_by_walls(bl, tr, walls, settled),
+            Constraint::Periodic(bl, tr, axes) => {
+                // leaving through a periodic side, the particle keeps its velocity on the other one
+                let size = tr - bl;
+                let mut wrapped = bl + (self.pos - bl).rem_euclid(size);
+                wrapped = Vec2::select(wrapped.cmpge(tr), bl, wrapped);
+                let shift = Vec2::select(axes, wrapped - self.pos, Vec2::ZERO);
+                self.pos += shift;
+                self.pos_old += shift;
+
+                let walls = Walls { left: !axes.x, right: !axes.x, bottom: !axes.y, top: !axes.y };
+                self.keep_inside(bl, tr, walls);
             },
+            Constraint::Open(..) => {},
+        }
+    }
+
+    /// Pushes the particle back behind the given walls of the rectangle from `bl` to `tr`.
+    fn keep_inside(&mut self, bl: Vec2, tr: Vec2, walls: Walls) {
+        let mut new = self.pos;
+        if walls.left {
+            new.x = new.x.max(bl.x + self.radius);
+        }
+        if walls.right {
+            new.x = new.x.min(tr.x - self.radius);
+        }
+        if walls.bottom {
+            new.y = new.y.max(bl.y + self.radius);
+        }
+        if walls.top {
+            new.y = new.y.min(tr.y - self.radius);
+        }
+        if new != self.pos {
+            self.hit_wall(new);
+        }
+    }
+
+    /// Keeps the particle on its side of each of the given walls, which only span the sides of
+    /// the rectangle from `bl` to `tr`, so particles that left it through an open side fall
+    /// past them. The side is the one of the `settled` position, collisions may push the
+    /// particle deep into a wall during the step.
+    fn keep_by_walls(&mut self, bl: Vec2, tr: Vec2, walls: Walls, settled: Vec2) {
+        let (s, r) = (settled, self.radius);
+        let along_x = s.x > bl.x - r && s.x < tr.x + r; // level with the bottom and the top
+        let along_y = s.y > bl.y - r && s.y < tr.y + r; // level with the left and the right
+        let mut new = self.pos;
+        if walls.left && along_y {
+            new.x = if s.x >= bl.x { new.x.max(bl.x + r) } else { new.x.min(bl.x - r) };
+        }
+        if walls.right && along_y {
+            new.x = if s.x <= tr.x { new.x.min(tr.x - r) } else { new.x.max(tr.x + r) };
+        }
+        if walls.bottom && along_x {
+            new.y = if s.y >= bl.y { new.y.max(bl.y + r) } else { new.y.min(bl.y - r) };
+        }
+        if walls.top && along_x {
+            new.y = if s.y <= tr.y { new.y.min(tr.y - r) } else { new.y.max(tr.y + r) };
+        }
+        if new != self.pos {
+            self.hit_wall(new);
+        }
+    }
+
+    /// Pushes the particle out of every collider it penetrates, moving colliders take it along.
+    /// Polylines it crossed since the `settled` position push it back to that side.
+    pub fn apply_colliders(&mut self, colliders: &[Collider], dt: f32, settled: Vec2) {
+        if self.mode != ParticleMode::Dynamic {
+            return;
+        }
+        for collider in colliders {
+            if let Some((normal, depth)) = collider.contact_along(settled, self.pos, self.radius) {
+                let wall_vel = collider.surface_velocity(self.pos, normal) * dt;
+                self.hit_moving_wall(self.pos + normal * depth, wall_vel);
+            }
         }
     }
 }
 
+
+/// Factor that scales the tangential velocity `vt` down by at most `max_change` (Coulomb friction).
+pub(crate) fn friction_scale(vt: Vec2, max_change: f32) -> f32 {
+    let speed = vt.length();
+    if speed > 0. {
+        1. - (max_change / speed).min(1.)
+    } else {
+        1.
+    }
+}
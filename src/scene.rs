@@ -1,4 +1,3 @@
-use glam::{Vec2, vec2};
 use iced::mouse;
 use iced::widget::shader::{self, wgpu};
 use iced::Rectangle;
@@ -11,20 +10,27 @@ use pipeline::Uniforms;
 mod camera;
 pub use camera::{Camera, MAX_FOV};
 
-use crate::particle::Particle;
-use crate::solver::{self, Connection, Simulation};
+use verlet_integration::material::{MaterialId, MaterialRegistry};
+use verlet_integration::particle::Particle;
+use verlet_integration::recording::{Input, Player, Recorder, Recording, KEYFRAME_INTERVAL};
+use verlet_integration::solver::{self, Parameters, Simulation};
 
 #[derive(Clone)]
 pub struct Scene {
     pub camera: Camera,
     pub simulation: Simulation,
+    pub recorder: Option<Recorder>,
+    pub player: Option<Player>, // replay mode, the live simulation is kept untouched
 }
 
 impl Scene {
-    pub fn new(number: usize, constraint: solver::Constraint) -> Self {
+    pub fn new(number: usize, constraint: solver::Constraint, materials: MaterialRegistry) -> Self {
         let mut scene = Self { 
             camera: Camera::default(), 
-            simulation: Simulation::new(constraint, 2.*solver::PARTICLE_SIZE, &[], &[])};
+            simulation: Simulation::new(constraint, 2.*solver::PARTICLE_SIZE, &[], &[]).with_materials(materials),
+            recorder: None,
+            player: None,
+        };
 
         scene.change_number(number);
 
@@ -32,11 +38,64 @@ impl Scene {
     }
 
     pub fn update(&mut self, dt: f32) {
-        self.simulation.solve(dt);
+        self.input(Input::Solve(dt));
     }
 
     pub fn change_number(&mut self, number: usize) {
-        self.simulation.change_number(number)
+        self.input(Input::ChangeNumber(number));
+    }
+
+    pub fn set_parameters(&mut self, parameters: Parameters) {
+        self.input(Input::SetParameters(parameters));
+    }
+
+    pub fn set_spawn_material(&mut self, material: MaterialId) {
+        self.input(Input::SetSpawnMaterial(material));
+    }
+
+    /// The simulation being shown: the replayed one in replay mode, the live one otherwise.
+    pub fn current(&self) -> &Simulation {
+        match &self.player {
+            Some(player) => &player.simulation,
+            None => &self.simulation,
+        }
+    }
+
+    fn input(&mut self, input: Input) {
+        if self.player.is_some() {
+            return;
+        }
+        match &mut self.recorder {
+            Some(recorder) => {
+                if let Err(err) = recorder.record(&mut self.simulation, input) {
+                    eprintln!("recording stopped: {err:#}");
+                    self.recorder = None;
+                }
+            }
+            None => {
+                if let Err(err) = input.apply(&mut self.simulation) {
+                    eprintln!("input ignored: {err:#}");
+                }
+            }
+        }
+    }
+
+    pub fn start_recording(&mut self) -> anyhow::Result<()> {
+        self.recorder = Some(Recorder::new(&self.simulation, KEYFRAME_INTERVAL)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recorder.take().map(Recorder::finish)
+    }
+
+    pub fn start_replay(&mut self, recording: Recording) -> anyhow::Result<()> {
+        self.player = Some(Player::new(recording)?);
+        Ok(())
+    }
+
+    pub fn stop_replay(&mut self) {
+        self.player = None;
     }
 }
 
@@ -51,7 +110,8 @@ impl<Message> shader::Program<Message> for Scene {
         bounds: Rectangle,
     ) -> Self::Primitive {
         Primitive::new(
-            &self.simulation.particles,
+            &self.current().particles,
+            &self.current().materials,
             &self.camera,
             bounds,
         )
@@ -68,13 +128,14 @@ pub struct Primitive {
 impl Primitive {
     pub fn new(
         particles: &[Particle],
+        materials: &MaterialRegistry,
         camera: &Camera,
         bounds: Rectangle,
     ) -> Self {
         Self {
             particles: particles
                 .iter()
-                .map(particle::Raw::from_particle)
+                .map(|p| particle::Raw::from_particle(p, materials))
                 .collect::<Vec<particle::Raw>>(),
             uniforms: Uniforms::new(camera, bounds)
         }
@@ -3,7 +3,8 @@ use iced::widget::shader::wgpu;
 use glam::vec2;
 
 use super::vertex::Vertex;
-use crate::particle::Particle;
+use verlet_integration::material::MaterialRegistry;
+use verlet_integration::particle::Particle;
 
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, Debug)]
 #[repr(C)]
@@ -11,16 +12,19 @@ pub struct Raw {
     size: f32,
     pos: glam::Vec2,
     texture: u32, 
+    colour: [f32; 4],
 }
 
 impl Raw {
-    const ATTRIBS: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+    const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
         // size
         2 => Float32,
         // position
         3 => Float32x2,
         // texture index
-        4 => Uint32
+        4 => Uint32,
+        // material colour
+        5 => Float32x4
     ];
 
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
@@ -33,11 +37,12 @@ impl Raw {
 }
 
 impl Raw {
-    pub fn from_particle(particle: &Particle) -> Raw {
+    pub fn from_particle(particle: &Particle, materials: &MaterialRegistry) -> Raw {
         Raw {
             size: particle.radius,
             pos: particle.pos,
-            texture: particle.texture
+            texture: particle.texture,
+            colour: materials.get(particle.material).map_or([1.; 4], |m| m.colour),
         }
     }
 
@@ -15,12 +15,14 @@ struct ParticleInput {
     @location(2) size: f32, 
     @location(3) position: vec2<f32>,
     @location(4) texture: u32,
+    @location(5) colour: vec4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) uv: vec2<f32>,
     @location(1) texture: u32,
+    @location(2) colour: vec4<f32>,
 }
 
 @vertex
@@ -31,6 +33,7 @@ fn vs_main(
     var out: VertexOutput;
     out.uv = vertex.uv;
     out.texture = particle.texture;
+    out.colour = particle.colour;
     let world_position = vec4<f32>(vertex.position*particle.size + particle.position, 0.0, 1.0);
     out.clip_position = uniforms.projection * world_position;
     return out;
@@ -45,7 +48,7 @@ var sampler_array: binding_array<sampler>;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(
+    return in.colour * textureSample(
         texture_array[in.texture], 
         sampler_array[in.texture], 
         in.uv);
@@ -0,0 +1,274 @@
+//! Versioned on-disk format for [`Simulation`] state.
+//!
+//! A scene is stored either as human-readable JSON or as a compact binary blob
+//! (`SCENE_MAGIC` followed by bincode). [`Simulation::load`] detects the format by
+//! the magic bytes, [`Simulation::save`] picks it by the file extension.
+//!
+//! JSON scenes of older versions still load, fields they lack get their defaults.
+//! Binary scenes are not self-describing and are only readable by the same version.
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::collider::Collider;
+use crate::handle::ParticleIds;
+use crate::material::{MaterialId, MaterialRegistry, TEXTURE_COUNT};
+use crate::particle::Particle;
+use crate::solver::{Angle, BroadPhase, Connection, Constraint, Parameters, Simulation, MAX_GRID_LEVEL};
+
+/// Current version of the scene format. Files with a newer version are rejected.
+pub const SCENE_VERSION: u32 = 17;
+/// Leading bytes of a binary scene file.
+pub const SCENE_MAGIC: &[u8; 4] = b"VRLS";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SceneFormat {
+    Json,
+    Binary,
+}
+
+impl SceneFormat {
+    /// `.json` files are written as JSON, everything else as binary.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => SceneFormat::Json,
+            _ => SceneFormat::Binary,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SceneFile<'a> {
+    version: u32,
+    constraint: Constraint,
+    #[serde(default)]
+    colliders: Cow<'a, [Collider]>,
+    cell_size: f32,
+    #[serde(default)]
+    broad_phase: BroadPhase,
+    #[serde(default)]
+    parameters: Parameters,
+    #[serde(default)]
+    materials: Cow<'a, MaterialRegistry>,
+    #[serde(default)]
+    spawn_material: MaterialId,
+    particles: Cow<'a, [Particle]>,
+    connections: Cow<'a, [Connection]>,
+    #[serde(default)]
+    angles: Cow<'a, [Angle]>,
+    #[serde(default)]
+    ids: Cow<'a, ParticleIds>, // empty in older scenes, the particles then get fresh ids
+    #[serde(default)]
+    kill_plane: Option<f32>,
+    #[serde(default)]
+    steps: usize, // decides when the particles are sorted next
+    #[serde(default)]
+    rng: Option<ChaCha8Rng>, // lets a loaded deterministic simulation continue exactly
+}
+
+impl SceneFile<'_> {
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            self.version <= SCENE_VERSION,
+            "scene version {} is newer than the supported version {}",
+            self.version,
+            SCENE_VERSION
+        );
+        ensure!(
+            self.cell_size.is_finite() && self.cell_size > 0.,
+            "invalid cell size {}",
+            self.cell_size
+        );
+        validate_parameters(&self.parameters)?;
+        validate_kill_plane(self.kill_plane)?;
+        validate_constraint(&self.constraint)?;
+
+        for (k, collider) in self.colliders.iter().enumerate() {
+            collider.validate().with_context(|| format!("collider #{k}"))?;
+        }
+
+        self.materials.validate()?;
+        ensure!(
+            self.materials.get(self.spawn_material).is_some(),
+            "spawn material {} does not exist",
+            self.spawn_material
+        );
+        let max_radius = 0.5 * self.cell_size * (1u64 << MAX_GRID_LEVEL) as f32;
+        for (i, p) in self.particles.iter().enumerate() {
+            validate_particle(p, &self.materials).with_context(|| format!("particle {i}"))?;
+            ensure!(
+                p.radius <= max_radius,
+                "particle {i} of radius {} is too large for the cell size {}",
+                p.radius,
+                self.cell_size
+            );
+        }
+
+        let n = self.particles.len();
+        for (k, &Connection { i, j, .. }) in self.connections.iter().enumerate() {
+            if i >= n || j >= n {
+                bail!("connection #{k} ({i}, {j}) points at a non-existent particle (only {n} particles)");
+            }
+            if i == j {
+                bail!("connection #{k} links particle {i} to itself");
+            }
+        }
+        for (k, a) in self.angles.iter().enumerate() {
+            let indices = [a.i, a.j, a.k];
+            if indices.iter().any(|&index| index >= n) {
+                bail!("angle #{k} {indices:?} points at a non-existent particle (only {n} particles)");
+            }
+            if a.i == a.j || a.j == a.k || a.i == a.k {
+                bail!("angle #{k} {indices:?} needs three different particles");
+            }
+            if !a.has_valid_limits() {
+                bail!("angle #{k} has the invalid limits {:?}, they need -PI <= min <= max <= PI", a.limits);
+            }
+        }
+        if !self.ids.is_empty() {
+            self.ids.validate(n)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn validate_parameters(parameters: &Parameters) -> Result<()> {
+    let Parameters { gravity, damping, sub_ticks, frame_dt, iterations, .. } = *parameters;
+    ensure!(
+        gravity.is_finite() && damping.is_finite() && frame_dt.is_finite() && frame_dt > 0. && sub_ticks > 0 && iterations > 0,
+        "invalid parameters {parameters:?}"
+    );
+    Ok(())
+}
+
+pub(crate) fn validate_kill_plane(kill_plane: Option<f32>) -> Result<()> {
+    ensure!(kill_plane.is_none_or(f32::is_finite), "invalid kill plane {kill_plane:?}");
+    Ok(())
+}
+
+pub(crate) fn validate_constraint(constraint: &Constraint) -> Result<()> {
+    let (bl, tr) = constraint.bounds();
+    ensure!(bl.is_finite() && tr.is_finite() && bl.x < tr.x && bl.y < tr.y, "invalid constraint bounds {bl} {tr}");
+    Ok(())
+}
+
+pub(crate) fn validate_particle(p: &Particle, materials: &MaterialRegistry) -> Result<()> {
+    ensure!(materials.get(p.material).is_some(), "non-existent material {}", p.material);
+    ensure!(
+        p.pos.is_finite() && p.pos_old.is_finite() && p.acc.is_finite(),
+        "non-finite position {} (previous {}, acceleration {})",
+        p.pos,
+        p.pos_old,
+        p.acc
+    );
+    ensure!(
+        p.mass.is_finite() && p.mass > 0. && p.radius.is_finite() && p.radius > 0.,
+        "needs a positive mass and radius, not {} and {}",
+        p.mass,
+        p.radius
+    );
+    ensure!(p.texture < TEXTURE_COUNT, "uses the texture {}, only {TEXTURE_COUNT} textures exist", p.texture);
+    Ok(())
+}
+
+impl Simulation {
+    /// Saves the simulation to `path`, as JSON if the extension is `.json` and in the binary format otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("cannot create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        self.write_scene(&mut writer, SceneFormat::from_path(path))?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Loads a simulation saved with [`Simulation::save`] in either format.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+        Simulation::read_scene(BufReader::new(file))
+            .with_context(|| format!("cannot load scene {}", path.display()))
+    }
+
+    pub fn write_scene(&self, mut writer: impl Write, format: SceneFormat) -> Result<()> {
+        let ids = if self.ids.len() == self.particles.len() {
+            Cow::Borrowed(&self.ids)
+        } else {
+            // particles were pushed since the last sync
+            let mut ids = self.ids.clone();
+            ids.resize(self.particles.len());
+            Cow::Owned(ids)
+        };
+        let scene = SceneFile {
+            version: SCENE_VERSION,
+            constraint: self.constraint,
+            colliders: Cow::Borrowed(&self.colliders),
+            cell_size: self.cell_size,
+            broad_phase: self.broad_phase,
+            parameters: self.parameters,
+            materials: Cow::Borrowed(&self.materials),
+            spawn_material: self.spawn_material,
+            particles: Cow::Borrowed(&self.particles),
+            connections: Cow::Borrowed(&self.connections),
+            angles: Cow::Borrowed(&self.angles),
+            ids,
+            kill_plane: self.kill_plane,
+            steps: self.steps,
+            rng: Some(self.rng.clone()),
+        };
+        match format {
+            SceneFormat::Json => serde_json::to_writer_pretty(writer, &scene)?,
+            SceneFormat::Binary => {
+                writer.write_all(SCENE_MAGIC)?;
+                bincode::serialize_into(writer, &scene)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_scene(mut reader: impl Read) -> Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let scene: SceneFile = match data.strip_prefix(SCENE_MAGIC) {
+            Some(binary) => {
+                // the version is the first field, so it can be read on its own
+                let version: u32 = bincode::deserialize(binary)?;
+                ensure!(
+                    version == SCENE_VERSION,
+                    "binary scene version {version} is not supported, expected {SCENE_VERSION}"
+                );
+                bincode::deserialize(binary)?
+            }
+            None => serde_json::from_slice(&data)?,
+        };
+        scene.validate()?;
+
+        let mut simulation = Simulation::new(
+            scene.constraint,
+            scene.cell_size,
+            &scene.particles,
+            &scene.connections,
+        )
+        .with_colliders(scene.colliders.into_owned())
+        .with_broad_phase(scene.broad_phase)
+        .with_parameters(scene.parameters)
+        .with_materials(scene.materials.into_owned());
+        simulation.angles = scene.angles.into_owned();
+        simulation.spawn_material = scene.spawn_material;
+        if !scene.ids.is_empty() {
+            simulation.ids = scene.ids.into_owned();
+        }
+        simulation.kill_plane = scene.kill_plane;
+        simulation.steps = scene.steps;
+        if let Some(rng) = scene.rng {
+            simulation.rng = rng;
+        }
+        Ok(simulation)
+    }
+}
@@ -0,0 +1,219 @@
+//! Signed distance fields for colliders of arbitrary shape.
+//!
+//! A [`DistanceField`] is a grid of signed distances, negative inside the solid, that is
+//! sampled bilinearly. It is computed from a black-and-white image, where dark pixels are
+//! solid, or baked from an analytic [`Sdf`] built by combining primitives.
+
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use glam::{vec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+/// Analytic signed distance function, combine primitives with the methods.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Sdf {
+    Circle { center: Vec2, radius: f32 },
+    Rect { center: Vec2, half_size: Vec2 },
+    Capsule { a: Vec2, b: Vec2, radius: f32 }, // segment with rounded ends
+    Union(Box<Sdf>, Box<Sdf>),
+    Intersection(Box<Sdf>, Box<Sdf>),
+    Difference(Box<Sdf>, Box<Sdf>), // the first shape with the second one cut out
+    Rounded(Box<Sdf>, f32), // grown by the radius, with rounded corners
+}
+
+impl Sdf {
+    pub fn union(self, other: Sdf) -> Sdf {
+        Sdf::Union(Box::new(self), Box::new(other))
+    }
+
+    pub fn intersection(self, other: Sdf) -> Sdf {
+        Sdf::Intersection(Box::new(self), Box::new(other))
+    }
+
+    pub fn difference(self, other: Sdf) -> Sdf {
+        Sdf::Difference(Box::new(self), Box::new(other))
+    }
+
+    pub fn rounded(self, radius: f32) -> Sdf {
+        Sdf::Rounded(Box::new(self), radius)
+    }
+
+    pub fn distance(&self, pos: Vec2) -> f32 {
+        match self {
+            Sdf::Circle { center, radius } => pos.distance(*center) - radius,
+            Sdf::Rect { center, half_size } => {
+                let q = (pos - *center).abs() - *half_size;
+                q.max(Vec2::ZERO).length() + q.max_element().min(0.)
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let ab = *b - *a;
+                let t = ((pos - *a).dot(ab) / ab.length_squared()).clamp(0., 1.);
+                let closest = if t.is_finite() { *a + ab * t } else { *a };
+                pos.distance(closest) - radius
+            }
+            Sdf::Union(a, b) => a.distance(pos).min(b.distance(pos)),
+            Sdf::Intersection(a, b) => a.distance(pos).max(b.distance(pos)),
+            Sdf::Difference(a, b) => a.distance(pos).max(-b.distance(pos)),
+            Sdf::Rounded(a, radius) => a.distance(pos) - radius,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DistanceField {
+    pub origin: Vec2, // world position of the first sample, the bottom-left one
+    pub cell_size: f32, // distance between neighbouring samples
+    pub width: usize,
+    pub height: usize,
+    pub distances: Vec<f32>, // row by row from the bottom
+}
+
+impl DistanceField {
+    /// Samples `sdf` on a grid covering the rectangle from `bl` to `tr`.
+    pub fn from_sdf(sdf: &Sdf, bl: Vec2, tr: Vec2, cell_size: f32) -> Result<Self> {
+        ensure!(bl.is_finite() && tr.is_finite() && bl.x < tr.x && bl.y < tr.y, "invalid distance field bounds {bl} {tr}");
+        ensure!(cell_size.is_finite() && cell_size > 0., "invalid distance field cell size {cell_size}");
+        let size = ((tr - bl) / cell_size).ceil();
+        let (width, height) = (size.x as usize + 1, size.y as usize + 1);
+        let distances = (0..height)
+            .flat_map(|y| (0..width).map(move |x| vec2(x as f32, y as f32)))
+            .map(|cell| sdf.distance(bl + cell * cell_size))
+            .collect();
+        let field = Self { origin: bl, cell_size, width, height, distances };
+        field.validate()?;
+        Ok(field)
+    }
+
+    /// Loads an image where pixels darker than mid-grey are solid. Every pixel becomes a
+    /// sample `cell_size` apart, with the bottom-left pixel at `origin`.
+    pub fn from_image(path: impl AsRef<Path>, origin: Vec2, cell_size: f32) -> Result<Self> {
+        let path = path.as_ref();
+        let image = image::open(path).with_context(|| format!("cannot load image {}", path.display()))?;
+        let image = image.to_luma8();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        // image rows go from the top, field rows from the bottom
+        let solid: Vec<bool> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, height - 1 - y)))
+            .map(|(x, y)| image.get_pixel(x as u32, y as u32).0[0] < 128)
+            .collect();
+        Self::from_mask(&solid, width, height, origin, cell_size).with_context(|| format!("invalid image {}", path.display()))
+    }
+
+    /// Computes the field of a mask of solid samples given row by row from the bottom.
+    pub fn from_mask(solid: &[bool], width: usize, height: usize, origin: Vec2, cell_size: f32) -> Result<Self> {
+        ensure!(solid.len() == width * height, "mask has {} samples instead of {width}x{height}", solid.len());
+        ensure!(width >= 2 && height >= 2, "distance field needs at least 2x2 samples, got {width}x{height}");
+        ensure!(cell_size.is_finite() && cell_size > 0., "invalid distance field cell size {cell_size}");
+        let outside = distance_transform(solid, width, height, true);
+        let inside = distance_transform(solid, width, height, false);
+        // the boundary lies halfway between a solid sample and an empty one
+        let distances = solid
+            .iter()
+            .zip(outside.iter().zip(inside.iter()))
+            .map(|(&solid, (&outside, &inside))| {
+                if solid {
+                    (0.5 - inside) * cell_size
+                } else {
+                    (outside - 0.5) * cell_size
+                }
+            })
+            .collect();
+        let field = Self { origin, cell_size, width, height, distances };
+        field.validate()?;
+        Ok(field)
+    }
+
+    fn at(&self, x: usize, y: usize) -> f32 {
+        self.distances[y * self.width + x]
+    }
+
+    /// Bilinearly interpolated distance at `pos` and the normalized gradient, pointing away
+    /// from the solid. Outside of the grid the distance to the grid is added.
+    pub fn sample(&self, pos: Vec2) -> (f32, Vec2) {
+        let max = vec2((self.width - 1) as f32, (self.height - 1) as f32);
+        let cell = ((pos - self.origin) / self.cell_size).clamp(Vec2::ZERO, max);
+        let outside = pos.distance(self.origin + cell * self.cell_size);
+
+        let (x, y) = ((cell.x as usize).min(self.width - 2), (cell.y as usize).min(self.height - 2));
+        let (fx, fy) = (cell.x - x as f32, cell.y - y as f32);
+        let (v00, v10, v01, v11) = (self.at(x, y), self.at(x + 1, y), self.at(x, y + 1), self.at(x + 1, y + 1));
+        let bottom = v00 + (v10 - v00) * fx;
+        let top = v01 + (v11 - v01) * fx;
+        let distance = bottom + (top - bottom) * fy;
+        let gradient = vec2((v10 - v00) * (1. - fy) + (v11 - v01) * fy, top - bottom);
+        (distance + outside, gradient.try_normalize().unwrap_or(Vec2::Y))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(self.width >= 2 && self.height >= 2, "distance field needs at least 2x2 samples");
+        ensure!(
+            self.distances.len() == self.width * self.height,
+            "distance field has {} samples instead of {}x{}",
+            self.distances.len(),
+            self.width,
+            self.height
+        );
+        ensure!(self.cell_size.is_finite() && self.cell_size > 0., "invalid distance field cell size {}", self.cell_size);
+        ensure!(
+            self.origin.is_finite() && self.distances.iter().all(|d| d.is_finite()),
+            "distance field has non-finite values"
+        );
+        Ok(())
+    }
+}
+
+/// Distance in samples from every sample to the closest one whose mask equals `target`.
+fn distance_transform(mask: &[bool], width: usize, height: usize, target: bool) -> Vec<f32> {
+    const FAR: f32 = 1e20;
+    let mut squared: Vec<f32> = mask.iter().map(|&m| if m == target { 0. } else { FAR }).collect();
+
+    let n = width.max(height);
+    let (mut f, mut d, mut v, mut z) = (vec![0.; n], vec![0.; n], vec![0; n], vec![0.; n + 1]);
+    for x in 0..width {
+        for y in 0..height {
+            f[y] = squared[y * width + x];
+        }
+        squared_distance_1d(&f[..height], &mut d, &mut v, &mut z);
+        for y in 0..height {
+            squared[y * width + x] = d[y];
+        }
+    }
+    for y in 0..height {
+        f[..width].copy_from_slice(&squared[y * width..(y + 1) * width]);
+        squared_distance_1d(&f[..width], &mut d, &mut v, &mut z);
+        squared[y * width..(y + 1) * width].copy_from_slice(&d[..width]);
+    }
+    squared.into_iter().map(f32::sqrt).collect()
+}
+
+/// Exact squared distance transform of a sampled function along one line, computed as the
+/// lower envelope of parabolas (Felzenszwalb and Huttenlocher).
+fn squared_distance_1d(f: &[f32], d: &mut [f32], v: &mut [usize], z: &mut [f32]) {
+    let intersection = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2 * q - 2 * p) as f32
+    };
+    let mut k = 0;
+    v[0] = 0;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+    for q in 1..f.len() {
+        let mut s = intersection(q, v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersection(q, v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f32::INFINITY;
+    }
+    k = 0;
+    for (q, d) in d.iter_mut().enumerate().take(f.len()) {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let offset = q as f32 - v[k] as f32;
+        *d = offset * offset + f[v[k]];
+    }
+}
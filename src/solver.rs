@@ -1,28 +1,250 @@
-use std::{
-    borrow::Borrow,
-    f32::consts::PI,
-    ops::{Index, IndexMut, Range},
-    time::Instant,
-};
-
-use glam::{vec2, Vec2};
-use rand::Rng;
-use rayon::prelude::*;
+use std::{f32::consts::PI, hash::Hasher};
 
-use verlet_integration::multithreaded::{self, UnsafeMultithreadedArray};
+use glam::{vec2, BVec2, IVec2, Vec2};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
 
-use crate::particle::{Particle, METAL, SAND};
+use crate::grid::{GridLevel, HashLevel, LevelCells};
+#[cfg(debug_assertions)]
+use crate::multithreaded::AccessChecker;
+use crate::multithreaded::{pair_mut, split_at_bounds};
+use crate::collider::Collider;
+use crate::handle::{ParticleId, ParticleIds};
+use crate::material::{MaterialId, MaterialRegistry};
+use crate::particle::{friction_scale, Particle, ParticleMode};
 pub const MAX: u32 = 200000;
 pub const PARTICLE_SIZE: f32 = 0.1;
+pub const SUB_TICKS: usize = 8; // default number of `solve` calls per frame
+pub const FRAME_DT: f32 = 0.08; // default simulated time per frame
+pub const GRAVITY: Vec2 = vec2(0., -1.);
+pub const DAMPING: f32 = 10.; // default velocity damping per unit of time
+pub const SPAWN_MARGIN: f32 = 2. * PARTICLE_SIZE; // distance of spawned particles from the constraint bounds
+pub const COHESION_RANGE: f32 = 0.1; // cohesion acts up to this share of the radii beyond contact
+pub const MAX_GRID_LEVEL: usize = 30; // coarsest grid level, larger particles share it and only see neighbouring cells
+pub const STRESS_DECAY: f32 = 0.99; // share of the accumulated link stress kept per step
+
+/// A link between particles `i` and `j`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Connection {
+    pub i: usize,
+    pub j: usize,
+    pub link: Link,
+    #[serde(default)]
+    pub strength: Option<Strength>, // unbreakable if `None`
+    #[serde(default)]
+    pub stress: f32, // strain accumulated over the recent steps, decays by `STRESS_DECAY` per step
+}
+
+/// Limits beyond which a connection breaks.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Strength {
+    pub max_strain: f32, // relative deviation from the rest length in a single step
+    pub max_stress: f32, // accumulated strain, a sustained load breaks links below `max_strain`
+}
+
+impl Connection {
+    pub fn new(i: usize, j: usize, link: Link) -> Self {
+        Self {
+            i,
+            j,
+            link,
+            strength: None,
+            stress: 0.,
+        }
+    }
+
+    pub fn with_strength(mut self, max_strain: f32, max_stress: f32) -> Self {
+        self.strength = Some(Strength { max_strain, max_stress });
+        self
+    }
+
+    /// Relative deviation of the distance between `p1` and `p2` from the rest length,
+    /// zero for links without one.
+    pub fn strain(&self, p1: &Particle, p2: &Particle) -> f32 {
+        let Some(length) = self.link.rest_length() else {
+            return 0.;
+        };
+        let deviation = p1.pos.distance(p2.pos) - length;
+        let deviation = match self.link {
+            Link::Rope(_) => deviation.max(0.),
+            Link::Strut(_) => deviation.min(0.),
+            _ => deviation,
+        };
+        (deviation / length).abs()
+    }
+
+    /// Adds the current strain to the accumulated stress, returns whether the link breaks.
+    fn load(&mut self, p1: &Particle, p2: &Particle) -> bool {
+        let Some(strength) = self.strength else {
+            return false;
+        };
+        let strain = self.strain(p1, p2);
+        self.stress = self.stress * STRESS_DECAY + strain;
+        strain > strength.max_strain || self.stress > strength.max_stress
+    }
+}
+
+/// Keeps the angle at particle `j` between the arms to `i` and `k` near `target`.
+///
+/// Angles are measured counterclockwise from the arm `j -> i` to the arm `j -> k`, in
+/// `(-PI, PI]`. Each step closes `stiffness` of the difference to `target` and then clamps
+/// the angle into `limits`, so `stiffness: 0` with limits gives a free hinge with stops.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Angle {
+    pub i: usize,
+    pub j: usize, // the vertex
+    pub k: usize,
+    pub target: f32,
+    pub stiffness: f32, // 0..=1
+    #[serde(default)]
+    pub limits: Option<(f32, f32)>, // min and max angle
+}
+
+impl Angle {
+    pub fn new(i: usize, j: usize, k: usize, target: f32, stiffness: f32) -> Self {
+        Self {
+            i,
+            j,
+            k,
+            target,
+            stiffness,
+            limits: None,
+        }
+    }
+
+    /// Stops the angle at `min` and `max`.
+    ///
+    /// # Panics
+    /// If the limits are not finite values with `-PI <= min <= max <= PI`.
+    pub fn with_limits(mut self, min: f32, max: f32) -> Self {
+        self.limits = Some((min, max));
+        assert!(self.has_valid_limits(), "invalid angle limits ({min}, {max}), they need -PI <= min <= max <= PI");
+        self
+    }
+
+    /// Whether `limits` is either unset or a range `-PI <= min <= max <= PI`.
+    pub fn has_valid_limits(&self) -> bool {
+        self.limits.is_none_or(|(min, max)| (-PI..=PI).contains(&min) && (-PI..=PI).contains(&max) && min <= max)
+    }
+
+    /// Current angle between the arms.
+    pub fn angle(&self, particles: &[Particle]) -> f32 {
+        let vertex = particles[self.j].pos;
+        (particles[self.i].pos - vertex).angle_to(particles[self.k].pos - vertex)
+    }
+}
+
+/// Something that happened during a `solve` call.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// The connection exceeded its strength and was removed from `Simulation::connections`.
+    /// Its indices are those at the moment it broke, particles removed later in the same step
+    /// shift them, so use the ids of its ends to find the particles afterwards.
+    LinkBroken(Connection, [ParticleId; 2]),
+    /// The particle fell below `Simulation::kill_plane` and was removed.
+    ParticleRemoved(ParticleId),
+}
+
+/// Spatial structure used to find colliding particles.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BroadPhase {
+    /// Dense grid covering the constraint bounds, particles outside of them are put into edge cells.
+    #[default]
+    Grid,
+    /// Hash of occupied cells, works for unbounded worlds and uses memory proportional to the occupied area.
+    SpatialHash,
+}
+
+/// Order in which positional links are relaxed within an iteration.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConstraintOrder {
+    /// Insertion order.
+    #[default]
+    Sequential,
+    /// A new random permutation every iteration, avoids the drift of a fixed order.
+    Randomized,
+    /// Batches of links without shared particles, each one solved in parallel.
+    Coloured,
+}
+
+impl ConstraintOrder {
+    pub const ALL: [ConstraintOrder; 3] = [ConstraintOrder::Sequential, ConstraintOrder::Randomized, ConstraintOrder::Coloured];
+}
+
+impl std::fmt::Display for ConstraintOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConstraintOrder::Sequential => "sequential",
+            ConstraintOrder::Randomized => "randomized",
+            ConstraintOrder::Coloured => "coloured",
+        })
+    }
+}
+
+/// Global parameters of the integration, can be changed between steps.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Parameters {
+    pub gravity: Vec2,
+    pub damping: f32, // linear damping, 0 keeps the velocity
+    pub sub_ticks: usize, // number of `solve` calls per frame
+    pub frame_dt: f32, // simulated time per frame
+    #[serde(default = "one")]
+    pub iterations: usize, // relaxation passes over the positional links and angles per `solve`
+    #[serde(default)]
+    pub constraint_order: ConstraintOrder,
+    #[serde(default)]
+    pub sort_interval: usize, // `solve` calls between spatial sorts of the particles, 0 never sorts
+}
+
+fn one() -> usize {
+    1
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            gravity: GRAVITY,
+            damping: DAMPING,
+            sub_ticks: SUB_TICKS,
+            frame_dt: FRAME_DT,
+            iterations: 1,
+            constraint_order: ConstraintOrder::default(),
+            sort_interval: 0,
+        }
+    }
+}
+
+impl Parameters {
+    /// Time step of a single `solve` call.
+    pub fn dt(&self) -> f32 {
+        self.frame_dt / self.sub_ticks.max(1) as f32
+    }
+}
 
-pub type Connection = (usize, usize, Link);
 #[derive(Clone)]
 pub struct Simulation {
     pub constraint: Constraint,
+    pub colliders: Vec<Collider>, // static geometry inside the constraint
     pub particles: Vec<Particle>,
     pub connections: Vec<Connection>,
-    pub cell_size: f32,
-    pub grid: Grid<usize>,
+    pub angles: Vec<Angle>,
+    pub cell_size: f32, // cell size of the finest grid level, should fit the smallest particles
+    pub broad_phase: BroadPhase,
+    pub parameters: Parameters,
+    pub materials: MaterialRegistry,
+    pub spawn_material: MaterialId, // material of particles added by `change_number` and the shape helpers
+    pub grids: Vec<GridLevel>, // grid levels, each one with twice the cell size of the previous
+    pub hashes: Vec<HashLevel>, // same as `grids` for `BroadPhase::SpatialHash`
+    pub rng: ChaCha8Rng, // source of all randomness, seed it for reproducible runs
+    pub ids: ParticleIds, // stable handles of the particles
+    pub events: Vec<Event>, // what happened during the last `solve` call
+    pub steps: usize, // number of `solve` calls so far
+    pub kill_plane: Option<f32>, // particles below this height are removed
+    settled: Vec<Vec2>, // positions at the start of the current step
 }
 
 impl Simulation {
@@ -32,166 +254,707 @@ impl Simulation {
         particles: &[Particle],
         connections: &[Connection],
     ) -> Self {
-        let bounds = constraint.bounds();
-        let width: usize = ((bounds.1.x - bounds.0.x) / cell_size) as usize + 3;
-        let height: usize = ((bounds.1.y - bounds.0.y) / cell_size) as usize + 3;
-
+        let mut ids = ParticleIds::default();
+        ids.resize(particles.len());
         Self {
             constraint,
+            colliders: Vec::new(),
             particles: Vec::from(particles),
             connections: Vec::from(connections),
+            angles: Vec::new(),
             cell_size,
-            grid: Grid::new(width, height),
+            broad_phase: BroadPhase::default(),
+            parameters: Parameters::default(),
+            materials: MaterialRegistry::default(),
+            spawn_material: 0,
+            grids: Vec::new(),
+            hashes: Vec::new(),
+            rng: ChaCha8Rng::from_entropy(),
+            ids,
+            events: Vec::new(),
+            steps: 0,
+            kill_plane: None,
+            settled: Vec::new(),
+        }
+    }
+
+    /// Makes the simulation deterministic: stepping does not depend on the number of threads
+    /// or their scheduling, so the same seed and inputs give bit-identical particle states.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn with_colliders(mut self, colliders: impl Into<Vec<Collider>>) -> Self {
+        self.colliders = colliders.into();
+        self
+    }
+
+    pub fn with_kill_plane(mut self, height: f32) -> Self {
+        self.kill_plane = Some(height);
+        self
+    }
+
+    pub fn with_broad_phase(mut self, broad_phase: BroadPhase) -> Self {
+        self.broad_phase = broad_phase;
+        self
+    }
+
+    pub fn with_parameters(mut self, parameters: Parameters) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    /// Replaces the material registry, the spawn material falls back to the first one if it does not exist.
+    pub fn with_materials(mut self, materials: MaterialRegistry) -> Self {
+        if materials.get(self.spawn_material).is_none() {
+            self.spawn_material = 0;
         }
+        self.materials = materials;
+        self
     }
 
     fn populate_grid(&mut self) {
-        self.grid.clear();
-        for (i, particle) in self.particles.iter().enumerate() {
-            let p = self.get_cell(particle.pos);
-            self.grid.push(p, i);
+        let levels: Vec<usize> = self.particles.iter().map(|p| self.grid_level(p.radius)).collect();
+        let level_count = levels.iter().max().map_or(1, |&l| l + 1);
+        let level_size = |m: usize| self.cell_size * (1 << m) as f32;
+
+        match self.broad_phase {
+            BroadPhase::Grid => {
+                self.hashes.clear();
+                let bounds = self.constraint.bounds();
+                self.grids.truncate(level_count);
+                // levels built for other bounds or cell sizes, e.g. before the constraint changed
+                for (m, level) in self.grids.iter_mut().enumerate() {
+                    if level.bounds != bounds || level.cell_size != level_size(m) {
+                        *level = GridLevel::new(bounds, level_size(m));
+                    }
+                }
+                while self.grids.len() < level_count {
+                    self.grids.push(GridLevel::new(bounds, level_size(self.grids.len())));
+                }
+
+                for (m, level) in self.grids.iter_mut().enumerate() {
+                    let cells: Vec<((i32, i32), usize, bool)> = self
+                        .particles
+                        .iter()
+                        .enumerate()
+                        .filter(|&(i, _)| levels[i] <= m)
+                        .map(|(i, p)| (level.get_cell(bounds.0, p.pos), i, levels[i] == m))
+                        .collect();
+                    level.fill(cells);
+                }
+            }
+            BroadPhase::SpatialHash => {
+                self.grids.clear();
+                self.hashes.truncate(level_count);
+                for (m, level) in self.hashes.iter_mut().enumerate() {
+                    if level.cell_size != level_size(m) {
+                        *level = HashLevel::new(level_size(m));
+                    }
+                }
+                while self.hashes.len() < level_count {
+                    self.hashes.push(HashLevel::new(level_size(self.hashes.len())));
+                }
+
+                for (m, level) in self.hashes.iter_mut().enumerate() {
+                    let cells: Vec<((i32, i32), usize, bool)> = self
+                        .particles
+                        .iter()
+                        .enumerate()
+                        .filter(|&(i, _)| levels[i] <= m)
+                        .map(|(i, p)| (level.get_cell(p.pos), i, levels[i] == m))
+                        .collect();
+                    level.fill(cells);
+                }
+            }
         }
     }
 
-    fn get_cell(&self, pos: Vec2) -> (usize, usize) {
-        let bounds = self.constraint.bounds().0;
-        (
-            ((pos.x - bounds.x) / self.cell_size).max(0.) as usize + 1,
-            ((pos.y - bounds.y) / self.cell_size).max(0.) as usize + 1,
-        )
+    /// Index of the finest grid level whose cells can hold a particle of the given radius, at
+    /// most [`MAX_GRID_LEVEL`].
+    pub fn grid_level(&self, radius: f32) -> usize {
+        (2. * radius / self.cell_size).log2().ceil().clamp(0., MAX_GRID_LEVEL as f32) as usize
+    }
+
+    /// Advances the simulation by one frame: `sub_ticks` steps of `frame_dt / sub_ticks`.
+    pub fn step_frame(&mut self) {
+        let dt = self.parameters.dt();
+        for _ in 0..self.parameters.sub_ticks {
+            self.solve(dt);
+        }
     }
 
     pub fn solve(&mut self, dt: f32) {
+        let interval = self.parameters.sort_interval;
+        if interval > 0 && self.steps.is_multiple_of(interval) {
+            self.sort_particles();
+        }
+        self.steps += 1;
+
         // populate the grid with indexes of particles
         //let time = Instant::now();
         self.populate_grid(); // TODO: for some reason it's slow in debug mode
         //let elapsed = Instant::now() - time;
         //println!("populate time: {}", 8.*elapsed.as_nanos() as f32 / 1000000.);
 
+        self.events.clear();
+        self.ids.resize(self.particles.len());
+        self.settled.clear();
+        self.settled.extend(self.particles.iter().map(|p| p.pos));
         self.resolve_collisions();
-        self.resolve_connections();
+        self.resolve_seam_collisions();
+        self.resolve_constraints(dt);
 
-        self.particles.par_iter_mut().for_each(|p| {
-            p.apply_gravity();
-            p.update(dt);
-            p.apply_constraint(self.constraint);
+        for collider in self.colliders.iter_mut() {
+            collider.advance(dt);
+        }
+        let Parameters { gravity, damping, .. } = self.parameters;
+        self.particles.par_iter_mut().zip(self.settled.par_iter()).for_each(|(p, &settled)| {
+            p.apply_gravity(gravity);
+            p.update(dt, damping);
+            p.apply_constraint(self.constraint, settled);
+            p.apply_colliders(&self.colliders, dt, settled);
         });
+        self.remove_fallen();
+    }
+
+    /// Removes the particles below the kill plane, e.g. those that overflowed a container.
+    fn remove_fallen(&mut self) {
+        let Some(kill_plane) = self.kill_plane else {
+            return;
+        };
+        let fallen: Vec<usize> = (0..self.particles.len()).filter(|&i| self.particles[i].pos.y < kill_plane).collect();
+        if fallen.is_empty() {
+            return;
+        }
+        for &i in fallen.iter() {
+            if let Some(id) = self.ids.id(i) {
+                self.events.push(Event::ParticleRemoved(id));
+            }
+        }
+        self.remove_particles(fallen);
     }
 
     fn resolve_collisions(&mut self) {
-        let even: Vec<Range<usize>> = (1..self.grid.width - 1)
-            .filter(|i| i % 4 == 1)
-            .map(|i| i..std::cmp::min(i + 2, self.grid.width - 1))
-            .collect();
-        let odd: Vec<Range<usize>> = (1..self.grid.width - 1)
-            .filter(|i| i % 4 == 3)
-            .map(|i| i..std::cmp::min(i + 2, self.grid.width - 1))
-            .collect();
+        match self.broad_phase {
+            BroadPhase::Grid => {
+                for level in self.grids.iter() {
+                    Simulation::resolve_level_collisions(&mut self.particles, level);
+                }
+            }
+            BroadPhase::SpatialHash => {
+                for level in self.hashes.iter() {
+                    Simulation::resolve_level_collisions(&mut self.particles, level);
+                }
+            }
+        }
+    }
+
+    /// Resolves collisions of own and finer particles of a level with its own particles.
+    ///
+    /// The particles are copied in the storage order of the level, so every column is a
+    /// contiguous run. A task resolves cells of two "core" columns and touches particles of
+    /// their direct neighbours as well, so it gets exclusive access to a slice spanning
+    /// 4 columns. Windows of one phase follow each other without overlap and the second
+    /// phase shifts them by 2 columns to cover the remaining core columns.
+    fn resolve_level_collisions(particles: &mut [Particle], level: &impl LevelCells) {
+        let elements = level.elements();
+        if elements.is_empty() {
+            return;
+        }
+        let mut buffer: Vec<Particle> = elements.par_iter().map(|&i| particles[i]).collect();
+        #[cfg(debug_assertions)]
+        let mut checker = AccessChecker::new(particles.len());
+
+        let columns = level.columns();
+        for phase in [1, 3] {
+            let window_of = |col: i32| (col - phase + 1).div_euclid(4);
+            let mut windows: Vec<i32> = columns.iter().map(|&col| window_of(col)).collect();
+            windows.dedup();
+            let bounds: Vec<usize> = windows
+                .iter()
+                .map(|&w| level.column_start(4 * w + phase - 1))
+                .chain(std::iter::once(buffer.len()))
+                .collect();
+            let slices = split_at_bounds(&mut buffer, &bounds);
 
-        let groups = &[even, odd];
-
-        let particles = UnsafeMultithreadedArray::new(&mut self.particles); // create unsafe array that can be manipulated in threads
-        let grid: &Grid<usize> = self.grid.borrow();
-        
-        for group in groups {
-            group.par_iter().for_each(|range| {
-                for col in range.clone() {
-                    for row in 1..grid.height - 1 {
-                        let c = (col, row);
-                        for &i in grid[c].iter() {
+            windows
+                .par_iter()
+                .enumerate()
+                .zip(slices)
+                .zip(bounds.par_iter())
+                .for_each(|(((task, &w), slice), &start)| {
+                    #[cfg(not(debug_assertions))]
+                    let _ = task; // only the access checker tells the tasks apart
+                    let core = 4 * w + phase..4 * w + phase + 2;
+                    level.for_each_cell(core, |(col, row)| {
+                        for a in level.cell((col, row)) {
                             for dc in -1..=1 {
                                 for dr in -1..=1 {
-                                    let adj = ((col as isize + dc) as usize, (row as isize + dr) as usize,);
-                                    for &j in grid[adj].iter() {
-                                        if i == j { continue }
-                                        Simulation::resolve_collision(&mut particles.clone()[i], &mut particles.clone()[j]);
+                                    for b in level.own((col + dc, row + dr)) {
+                                        if a == b { continue }
+                                        #[cfg(debug_assertions)]
+                                        {
+                                            checker.claim(elements[a], task);
+                                            checker.claim(elements[b], task);
+                                        }
+                                        let (p1, p2) = pair_mut(slice, a - start, b - start);
+                                        Simulation::resolve_collision(p1, p2);
                                     }
                                 }
                             }
                         }
+                    });
+                });
+
+            #[cfg(debug_assertions)]
+            checker.reset();
+        }
+
+        for (p, &i) in buffer.iter().zip(elements) {
+            particles[i] = *p;
+        }
+    }
+
+    /// Resolves collisions between particles on opposite sides of periodic axes, which the
+    /// broad phase sees as far apart. Only particles near those sides are checked.
+    fn resolve_seam_collisions(&mut self) {
+        let periodic = self.constraint.periodic();
+        if !periodic.any() {
+            return;
+        }
+        let (bl, tr) = self.constraint.bounds();
+        let reach = 2. * self.particles.iter().map(|p| p.radius).fold(0., f32::max) * (1. + COHESION_RANGE);
+        if reach <= 0. {
+            return;
+        }
+        let near_seam = |pos: Vec2| {
+            let (low, high) = (pos.cmplt(bl + reach), pos.cmpgt(tr - reach));
+            ((low | high) & periodic).any()
+        };
+        let strip: Vec<usize> = (0..self.particles.len()).filter(|&i| near_seam(self.particles[i].pos)).collect();
+
+        // cells at least `reach` wide, so touching particles are in neighbouring cells
+        let counts = ((tr - bl) / reach).floor().as_ivec2().max(IVec2::ONE);
+        let cell_of = |pos: Vec2| ((pos - bl) / reach).floor().as_ivec2().clamp(IVec2::ZERO, counts - 1);
+        let mut cells: FxHashMap<IVec2, Vec<usize>> = FxHashMap::default();
+        for &i in strip.iter() {
+            cells.entry(cell_of(self.particles[i].pos)).or_default().push(i);
+        }
+
+        let mut neighbours = Vec::with_capacity(9);
+        for &a in strip.iter() {
+            let cell = cell_of(self.particles[a].pos);
+            neighbours.clear();
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let n = cell + IVec2::new(dx, dy);
+                    let n = IVec2::select(periodic, n.rem_euclid(counts), n);
+                    if !neighbours.contains(&n) {
+                        neighbours.push(n);
+                    }
+                }
+            }
+            for n in neighbours.iter() {
+                for &b in cells.get(n).into_iter().flatten() {
+                    // pairs that don't cross a seam were already handled by the broad phase
+                    if b <= a || self.constraint.image_shift(self.particles[a].pos, self.particles[b].pos) == Vec2::ZERO {
+                        continue;
+                    }
+                    let (p1, p2) = pair_mut(&mut self.particles, a, b);
+                    nearest_image(self.constraint, p1, p2, Simulation::resolve_collision);
+                }
+            }
+        }
+    }
+
+    /// Breaks overloaded connections and applies the force links once, then relaxes the
+    /// positional links and the angles `iterations` times in the configured order.
+    fn resolve_constraints(&mut self, dt: f32) {
+        let constraint = self.constraint;
+        let particles = &mut self.particles;
+        let events = &mut self.events;
+        let ids = &self.ids;
+        self.connections.retain_mut(|connection| {
+            if connection.i >= particles.len() || connection.j >= particles.len() {
+                return false;
+            }
+            let (p1, p2) = pair_mut(particles, connection.i, connection.j);
+            nearest_image(constraint, p1, p2, |p1, p2| {
+                if connection.load(p1, p2) {
+                    if let (Some(a), Some(b)) = (ids.id(connection.i), ids.id(connection.j)) {
+                        events.push(Event::LinkBroken(*connection, [a, b]));
                     }
+                    return false;
+                }
+                if !connection.link.is_positional() {
+                    Simulation::resolve_connection(p1, p2, connection.link, dt);
                 }
+                true
             })
+        });
+        let n = self.particles.len();
+        self.angles.retain(|a| a.i < n && a.j < n && a.k < n);
+
+        let mut positional: Vec<usize> = (0..self.connections.len())
+            .filter(|&c| self.connections[c].link.is_positional())
+            .collect();
+        let batches = match self.parameters.constraint_order {
+            ConstraintOrder::Coloured => self.colour_connections(&positional),
+            ConstraintOrder::Sequential | ConstraintOrder::Randomized => Vec::new(),
+        };
+        for _ in 0..self.parameters.iterations.max(1) {
+            match self.parameters.constraint_order {
+                ConstraintOrder::Sequential => self.relax_connections(&positional, dt),
+                ConstraintOrder::Randomized => {
+                    positional.shuffle(&mut self.rng);
+                    self.relax_connections(&positional, dt);
+                }
+                ConstraintOrder::Coloured => {
+                    for batch in batches.iter() {
+                        self.relax_batch(batch, dt);
+                    }
+                }
+            }
+            self.resolve_angles();
+        }
+    }
+
+    fn relax_connections(&mut self, order: &[usize], dt: f32) {
+        for &c in order {
+            let Connection { i, j, link, .. } = self.connections[c];
+            let (p1, p2) = pair_mut(&mut self.particles, i, j);
+            nearest_image(self.constraint, p1, p2, |p1, p2| Simulation::resolve_connection(p1, p2, link, dt));
+        }
+    }
+
+    /// Relaxes connections without shared particles in parallel: every task works on copies
+    /// of its two particles, which are written back afterwards.
+    fn relax_batch(&mut self, batch: &[usize], dt: f32) {
+        let particles = &self.particles;
+        let results: Vec<(Connection, Particle, Particle)> = batch
+            .par_iter()
+            .with_min_len(256)
+            .map(|&c| {
+                let connection = self.connections[c];
+                let (mut p1, mut p2) = (particles[connection.i], particles[connection.j]);
+                nearest_image(self.constraint, &mut p1, &mut p2, |p1, p2| {
+                    Simulation::resolve_connection(p1, p2, connection.link, dt)
+                });
+                (connection, p1, p2)
+            })
+            .collect();
+        for (connection, p1, p2) in results {
+            self.particles[connection.i] = p1;
+            self.particles[connection.j] = p2;
         }
     }
 
-    fn resolve_connections(&mut self) {
-        self.connections
-            .retain(|&(i, j, _)| i < self.particles.len() && j < self.particles.len());
-        for &(i, j, link) in self.connections.iter() {
-            let (i, j) = (std::cmp::min(i, j), std::cmp::max(i, j));
-            let (head, tail) = self.particles.split_at_mut(i + 1);
-            Simulation::resolve_connection(&mut head[i], &mut tail[j - i - 1], link);
+    /// Greedily splits the connections into batches in which no particle appears twice.
+    fn colour_connections(&self, connections: &[usize]) -> Vec<Vec<usize>> {
+        let mut used: Vec<u64> = vec![0; self.particles.len()]; // colours taken at each particle
+        let mut batches: Vec<Vec<usize>> = Vec::new();
+        let mut overflow = Vec::new();
+        for &c in connections {
+            let Connection { i, j, .. } = self.connections[c];
+            let colour = (!(used[i] | used[j])).trailing_zeros() as usize;
+            if colour == 64 {
+                overflow.push(c);
+                continue;
+            }
+            used[i] |= 1 << colour;
+            used[j] |= 1 << colour;
+            if colour == batches.len() {
+                batches.push(Vec::new());
+            }
+            batches[colour].push(c);
+        }
+        // particles with more than 64 links are rare, their remaining links get one batch each
+        batches.extend(overflow.into_iter().map(|c| vec![c]));
+        batches
+    }
+
+    /// Rotates the arms of every angle constraint around its vertex, sharing the rotation
+    /// between the movable ends.
+    fn resolve_angles(&mut self) {
+        for a in self.angles.iter() {
+            let vertex = self.particles[a.j].pos;
+            let arm = |index: usize| self.constraint.delta(vertex, self.particles[index].pos);
+            let angle = arm(a.i).angle_to(arm(a.k));
+            let mut change = wrap_angle(a.target - angle) * a.stiffness;
+            if let Some((min, max)) = a.limits {
+                // unlike `clamp`, doesn't panic on limits set directly to an empty or NaN range
+                change = (angle + change).max(min).min(max) - angle;
+            }
+            let (wi, wk) = (self.particles[a.i].mobility(), self.particles[a.k].mobility());
+            if change == 0. || wi + wk == 0. {
+                continue;
+            }
+            for (index, share) in [(a.i, -wi / (wi + wk)), (a.k, wk / (wi + wk))] {
+                let p = &mut self.particles[index];
+                let shift = self.constraint.image_shift(vertex, p.pos);
+                let pos = vertex + Vec2::from_angle(change * share).rotate(p.pos + shift - vertex) - shift;
+                p.set_position(pos, true);
+            }
         }
     }
 
+    /// Pushes overlapping particles apart along the normal, weighted by inverse mass (pinned
+    /// and kinematic particles are not pushed at all). If they are
+    /// approaching, their normal relative velocity is also reflected by the mean restitution and
+    /// the tangential part is reduced by friction (the geometric mean of both coefficients).
+    ///
+    /// Cohesive particles that are not touching but closer than [`COHESION_RANGE`] are pulled
+    /// together by the weaker of both cohesions.
     pub fn resolve_collision(p1: &mut Particle, p2: &mut Particle) {
-        let mut v = p1.pos - p2.pos;
-        if v.length() < p1.radius + p2.radius {
-            let overlap = (p1.radius + p2.radius - v.length());
-            let c1 = p2.mass / (p1.mass + p2.mass);
-            let c2 = p1.mass / (p1.mass + p2.mass);
-            v = v.normalize() * overlap;
-            p1.set_position(p1.pos + v * c1, true);
-            p2.set_position(p2.pos - v * c2, true);
+        let v = p1.pos - p2.pos;
+        let reach = p1.radius + p2.radius;
+        let (w1, w2) = (p1.inverse_mass(), p2.inverse_mass());
+        if w1 + w2 == 0. {
+            return;
+        }
+        let (c1, c2) = (w1 / (w1 + w2), w2 / (w1 + w2));
+        if v.length() < reach {
+            let overlap = reach - v.length();
+            // particles squeezed into the same corner can end up on exactly the same spot
+            let normal = v.try_normalize().unwrap_or(Vec2::Y);
+            let vel = (p1.pos - p1.pos_old) - (p2.pos - p2.pos_old);
+            p1.set_position(p1.pos + normal * overlap * c1, true);
+            p2.set_position(p2.pos - normal * overlap * c2, true);
+
+            let restitution = (p1.restitution + p2.restitution) / 2.;
+            let friction = (p1.friction * p2.friction).sqrt();
+            let vn = vel.dot(normal);
+            if vn < 0. && (restitution > 0. || friction > 0.) {
+                // velocities are implicit, so the change is applied to the old positions,
+                // the push apart already separated them by `overlap`
+                let bounce = (-(1. + restitution) * vn - overlap).max(0.);
+                let vt = vel - normal * vn;
+                let tangential = vt * (friction_scale(vt, friction * (overlap + bounce)) - 1.);
+                let change = tangential + normal * bounce;
+                p1.pos_old -= change * c1;
+                p2.pos_old += change * c2;
+            }
+        } else if v.length() < reach * (1. + COHESION_RANGE) {
+            let cohesion = p1.cohesion.min(p2.cohesion);
+            if cohesion > 0. {
+                let pull = v.normalize() * (v.length() - reach) * cohesion;
+                p1.set_position(p1.pos - pull * c1, true);
+                p2.set_position(p2.pos + pull * c2, true);
+            }
         }
     }
 
-    pub fn resolve_connection(p1: &mut Particle, p2: &mut Particle, link: Link) {
+    pub fn resolve_connection(p1: &mut Particle, p2: &mut Particle, link: Link, dt: f32) {
         match link {
             Link::Force(force) => {
                 let v = (p2.pos - p1.pos).normalize_or_zero();
-                p1.accelerate(v * force);
-                p2.accelerate(-v * force);
+                p1.accelerate(v * force * p1.mobility());
+                p2.accelerate(-v * force * p2.mobility());
+            }
+            Link::Rigid(length) => Simulation::keep_distance(p1, p2, length),
+            Link::Rope(length) => {
+                if p1.pos.distance(p2.pos) > length {
+                    Simulation::keep_distance(p1, p2, length);
+                }
+            }
+            Link::Strut(length) => {
+                if p1.pos.distance(p2.pos) < length {
+                    Simulation::keep_distance(p1, p2, length);
+                }
             }
-            Link::Rigid(length) => {
-                let mut v = p1.pos - p2.pos;
-                let overlap = (length - v.length()) / 2.;
-                v = overlap * v.normalize();
-                p1.set_position(p1.pos + v, true);
-                p2.set_position(p2.pos - v, true);
+            Link::Spring { rest, stiffness, damping } => {
+                let axis = (p2.pos - p1.pos).normalize_or_zero();
+                // Verlet velocities are the displacements of the last step
+                let displacement = (p2.pos - p2.pos_old) - (p1.pos - p1.pos_old);
+                let vel = if dt > 0. { displacement / dt } else { Vec2::ZERO };
+                let force = stiffness * (p1.pos.distance(p2.pos) - rest) + damping * vel.dot(axis);
+                p1.accelerate(axis * force * p1.inverse_mass());
+                p2.accelerate(-axis * force * p2.inverse_mass());
             }
         }
     }
 
+    /// Moves both particles to be `length` apart. The correction is shared equally,
+    /// unless one end does not move.
+    fn keep_distance(p1: &mut Particle, p2: &mut Particle, length: f32) {
+        let (w1, w2) = (p1.mobility(), p2.mobility());
+        if w1 + w2 == 0. {
+            return;
+        }
+        let mut v = p1.pos - p2.pos;
+        v = (length - v.length()) * v.try_normalize().unwrap_or(Vec2::Y);
+        p1.set_position(p1.pos + v * w1 / (w1 + w2), true);
+        p2.set_position(p2.pos - v * w2 / (w1 + w2), true);
+    }
+
     pub fn change_number(&mut self, number: usize) {
         if number < self.particles.len() {
-            self.particles.truncate(number);
+            self.remove_particles(number..self.particles.len());
         } else {
+            let area = self.spawn_area();
             while self.particles.len() < number {
-                let left = number - self.particles.len();
-                //if left >= 20 {
-                //    self.add_ring(0.3, 20);
-                //}
-                //else if left >= 4 {
-                //    self.add_square(1.0);
-                //}
-                //else if left >= 3 {
-                //    self.add_triangle(1.0);
-                //}
-                //else {self.add_particle(SAND.place(self.rnd_origin()));}
-                let mut bounds = self.constraint.bounds();
-                bounds.0.y = bounds.1.y * 0.8;
-                let pos = rnd_in_bounds(bounds, 2. * PARTICLE_SIZE);
-                if self.particles.len() % 10 == 0 {
-                    self.add_particle(SAND.place(pos));
-                } else {
-                    self.add_particle(SAND.place(pos));
-                }
+                let pos = rnd_in_bounds(&mut self.rng, area, SPAWN_MARGIN);
+                self.add_particle(self.materials.particle(self.spawn_material, pos));
             }
         }
     }
 
-    pub fn add_particle(&mut self, particle: Particle) {
+    /// Removes the given particles, keeping the order of the others. Connections and angles
+    /// of removed particles are dropped, the remaining ones are remapped. Returns the new
+    /// index of every old particle, `None` for removed ones. Indices past the last particle
+    /// are ignored.
+    pub fn remove_particles(&mut self, indices: impl IntoIterator<Item = usize>) -> Vec<Option<usize>> {
+        let mut removed = vec![false; self.particles.len()];
+        for i in indices {
+            if let Some(removed) = removed.get_mut(i) {
+                *removed = true;
+            }
+        }
+        let mut map = Vec::with_capacity(self.particles.len());
+        let mut next = 0;
+        for &removed in removed.iter() {
+            map.push((!removed).then_some(next));
+            next += usize::from(!removed);
+        }
+        self.remap_particles(&map, next);
+        map
+    }
+
+    /// Reorders the particles along a Z-order curve over the cells of the finest grid level,
+    /// so that particles close in space are close in memory and collision checks hit the cache.
+    /// Connections, angles and ids follow the particles. Returns the new index of every particle.
+    pub fn sort_particles(&mut self) -> Vec<Option<usize>> {
+        let (bl, _) = self.constraint.bounds();
+        let mut order: Vec<(u32, usize)> = self
+            .particles
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let cell = ((p.pos - bl) / self.cell_size).max(Vec2::ZERO).min(Vec2::splat(u16::MAX as f32));
+                (z_order(cell.x as u16, cell.y as u16), i)
+            })
+            .collect();
+        order.sort_unstable();
+
+        let mut map = vec![None; self.particles.len()];
+        for (new, &(_, i)) in order.iter().enumerate() {
+            map[i] = Some(new);
+        }
+        self.remap_particles(&map, order.len());
+        map
+    }
+
+    /// Moves every particle `i` to index `map[i]` and removes those mapped to `None`.
+    /// Connections and angles follow their particles or are dropped with them.
+    fn remap_particles(&mut self, map: &[Option<usize>], len: usize) {
+        self.ids.resize(self.particles.len());
+        let mut particles = vec![Particle::null(); len];
+        for (p, &new) in self.particles.iter().zip(map.iter()) {
+            if let Some(new) = new {
+                particles[new] = *p;
+            }
+        }
+        self.particles = particles;
+        self.ids.remap(map, len);
+
+        let new = |i: usize| map.get(i).copied().flatten();
+        self.connections.retain_mut(|c| match (new(c.i), new(c.j)) {
+            (Some(i), Some(j)) => {
+                (c.i, c.j) = (i, j);
+                true
+            }
+            _ => false,
+        });
+        self.angles.retain_mut(|a| match (new(a.i), new(a.j), new(a.k)) {
+            (Some(i), Some(j), Some(k)) => {
+                (a.i, a.j, a.k) = (i, j, k);
+                true
+            }
+            _ => false,
+        });
+    }
+
+    /// Area where `change_number` spawns particles: the top fifth of the constraint bounds,
+    /// or all of them if that is too thin to keep [`SPAWN_MARGIN`] from its edges.
+    pub fn spawn_area(&self) -> (Vec2, Vec2) {
+        let (bl, tr) = self.constraint.bounds();
+        let band = bl.y + 0.8 * (tr.y - bl.y);
+        if tr.y - band > 2. * SPAWN_MARGIN {
+            (vec2(bl.x, band), tr)
+        } else {
+            (bl, tr)
+        }
+    }
+
+    pub fn add_particle(&mut self, particle: Particle) -> ParticleId {
         self.particles.push(particle);
+        self.ids.resize(self.particles.len());
+        self.ids.id(self.particles.len() - 1).unwrap()
+    }
+
+    /// Current index of a particle, `None` if it was removed.
+    pub fn index_of(&self, id: ParticleId) -> Option<usize> {
+        self.ids.index(id)
+    }
+
+    /// Id of the particle at `index`. Particles pushed to `particles` directly get their ids
+    /// with the next `solve` or `add_particle` call.
+    pub fn id_of(&self, index: usize) -> Option<ParticleId> {
+        self.ids.id(index)
+    }
+
+    pub fn particle(&self, id: ParticleId) -> Option<&Particle> {
+        self.index_of(id).map(|i| &self.particles[i])
+    }
+
+    pub fn particle_mut(&mut self, id: ParticleId) -> Option<&mut Particle> {
+        self.index_of(id).map(|i| &mut self.particles[i])
+    }
+
+    /// Sets how particle `i` is moved, stopping it if it is no longer dynamic.
+    pub fn set_mode(&mut self, i: usize, mode: ParticleMode) {
+        let p = &mut self.particles[i];
+        p.mode = mode;
+        if mode != ParticleMode::Dynamic {
+            p.pos_old = p.pos;
+        }
+    }
+
+    /// Moves the kinematic particle `i` to `pos`, to be called before each `solve`.
+    pub fn drive(&mut self, i: usize, pos: Vec2) {
+        debug_assert_eq!(self.particles[i].mode, ParticleMode::Kinematic);
+        self.particles[i].drive(pos);
     }
 
     pub fn add_rib(&mut self, i: usize, j: usize, length: f32) {
-        self.connections.push((i, j, Link::Rigid(length)))
+        self.connections.push(Connection::new(i, j, Link::Rigid(length)))
     }
 
     pub fn add_spring(&mut self, i: usize, j: usize, force: f32) {
-        self.connections.push((i, j, Link::Force(force)))
+        self.connections.push(Connection::new(i, j, Link::Force(force)))
+    }
+
+    /// Adds a damped spring resting at the current distance of the particles.
+    pub fn add_damped_spring(&mut self, i: usize, j: usize, stiffness: f32, damping: f32) {
+        let rest = self.particles[i].pos.distance(self.particles[j].pos);
+        self.connections.push(Connection::new(i, j, Link::Spring { rest, stiffness, damping }))
+    }
+
+    pub fn add_rope(&mut self, i: usize, j: usize, max_length: f32) {
+        self.connections.push(Connection::new(i, j, Link::Rope(max_length)))
+    }
+
+    pub fn add_strut(&mut self, i: usize, j: usize, min_length: f32) {
+        self.connections.push(Connection::new(i, j, Link::Strut(min_length)))
+    }
+
+    /// Adds an angle constraint at `j` that keeps the current angle.
+    pub fn add_angle(&mut self, i: usize, j: usize, k: usize, stiffness: f32) {
+        let angle = Angle::new(i, j, k, 0., stiffness);
+        let target = angle.angle(&self.particles);
+        self.angles.push(Angle { target, ..angle });
     }
 
     pub fn add_triangle(&mut self, length: f32) {
@@ -208,136 +971,268 @@ impl Simulation {
     pub fn add_ring(&mut self, length: f32, number: usize) {
         let angle = PI / (number as f32);
         let radius = 0.5 * length / f32::atan(angle);
-        let center = rnd_in_bounds(self.constraint.bounds(), radius);
+        let center = rnd_in_bounds(&mut self.rng, self.constraint.bounds(), radius);
         let ind = self.particles.len();
         for i in 0..number {
             let alpha = 2. * PI * (i as f32) / (number as f32);
             let pos = center + glam::vec2(f32::cos(alpha), f32::sin(alpha)) * radius;
-            self.add_particle(SAND.place(pos));
+            self.add_particle(self.materials.particle(self.spawn_material, pos));
             self.add_rib(ind + i, ind + ((i + 1) % number), length);
         }
     }
 
-    fn rnd_origin(&self) -> Vec2 {
-        let bounds = self.constraint.bounds();
-        rnd_in_bounds(bounds, 2. * PARTICLE_SIZE)
+    /// Platform independent hash of the particles, connections, angles and moving colliders,
+    /// together with the random generator and step count that decide the constraint order,
+    /// sorts and spawns of the next steps. Equal hashes mean bit-identical states.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::default();
+        hasher.write_u64(self.particles.len() as u64);
+        for p in self.particles.iter() {
+            for v in [p.pos, p.pos_old, p.acc] {
+                hasher.write_u32(v.x.to_bits());
+                hasher.write_u32(v.y.to_bits());
+            }
+            hasher.write_u32(p.radius.to_bits());
+            hasher.write_u32(p.mass.to_bits());
+            hasher.write_u32(p.friction.to_bits());
+            hasher.write_u32(p.restitution.to_bits());
+            hasher.write_u32(p.cohesion.to_bits());
+            hasher.write_u32(p.material);
+            hasher.write_u8(p.mode as u8);
+            hasher.write_u32(p.texture);
+        }
+        hasher.write_u64(self.connections.len() as u64);
+        for c in self.connections.iter() {
+            hasher.write_u64(c.i as u64);
+            hasher.write_u64(c.j as u64);
+            let (kind, values) = match c.link {
+                Link::Force(force) => (0, vec![force]),
+                Link::Rigid(length) => (1, vec![length]),
+                Link::Spring { rest, stiffness, damping } => (2, vec![rest, stiffness, damping]),
+                Link::Rope(length) => (3, vec![length]),
+                Link::Strut(length) => (4, vec![length]),
+            };
+            hasher.write_u8(kind);
+            for value in values {
+                hasher.write_u32(value.to_bits());
+            }
+            if let Some(strength) = c.strength {
+                hasher.write_u32(strength.max_strain.to_bits());
+                hasher.write_u32(strength.max_stress.to_bits());
+            }
+            hasher.write_u32(c.stress.to_bits());
+        }
+        hasher.write_u64(self.angles.len() as u64);
+        for a in self.angles.iter() {
+            for index in [a.i, a.j, a.k] {
+                hasher.write_u64(index as u64);
+            }
+            let (min, max) = a.limits.unwrap_or((f32::NAN, f32::NAN));
+            for value in [a.target, a.stiffness, min, max] {
+                hasher.write_u32(value.to_bits());
+            }
+        }
+        // moving colliders are part of the state, their shapes are not
+        hasher.write_u64(self.colliders.len() as u64);
+        for c in self.colliders.iter() {
+            for value in [c.position.x, c.position.y, c.rotation, c.velocity.x, c.velocity.y, c.angular_velocity] {
+                hasher.write_u32(value.to_bits());
+            }
+        }
+        hasher.write_u64(self.steps as u64);
+        hasher.write(&self.rng.get_seed());
+        hasher.write_u64(self.rng.get_stream());
+        hasher.write(&self.rng.get_word_pos().to_le_bytes());
+        hasher.finish()
     }
 }
 
-pub fn rnd_in_bounds(bounds: (Vec2, Vec2), margin: f32) -> Vec2 {
-    Vec2::new(
-        rand::thread_rng().gen_range(bounds.0.x + margin..bounds.1.x - margin),
-        rand::thread_rng().gen_range(bounds.0.y + margin..bounds.1.y - margin),
-    )
-}
+// 64-bit FNV-1a, unlike `DefaultHasher` its output is stable across platforms and releases
+struct StateHasher(u64);
 
-#[derive(Clone, Copy)]
-pub enum Link {
-    Force(f32), // force
-    Rigid(f32), // constant length
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
 }
 
-const CELL_MAX: usize = 4;
-
-#[derive(Default, Clone)]
-pub struct GridCell<T>
-where
-    T: Clone + Copy + Default,
-{
-    pub len: usize,
-    pub elements: [T; CELL_MAX],
-}
+impl Hasher for StateHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
 
-impl<T> GridCell<T>
-where
-    T: Clone + Copy + Default,
-{
-    pub fn push(&mut self, elem: T) {
-        if self.len < CELL_MAX {
-            self.elements[self.len] = elem;
-            self.len += 1;
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001b3);
         }
     }
 
-    pub fn clear(&mut self) {
-        self.len = 0;
+    // the default implementations use native endianness
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
     }
 
-    pub fn iter(&self) -> std::slice::Iter<T> {
-        self.elements[0..self.len].iter()
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
     }
+}
 
-    pub fn iter_mut(&mut self) -> std::slice::IterMut<T> {
-        self.elements[0..self.len].iter_mut()
+/// Interleaves the bits of `x` and `y`, giving the position of the cell along a Z-order curve.
+fn z_order(x: u16, y: u16) -> u32 {
+    fn spread(v: u16) -> u32 {
+        let mut v = v as u32;
+        v = (v | (v << 8)) & 0x00ff_00ff;
+        v = (v | (v << 4)) & 0x0f0f_0f0f;
+        v = (v | (v << 2)) & 0x3333_3333;
+        (v | (v << 1)) & 0x5555_5555
     }
+    spread(x) | (spread(y) << 1)
 }
 
-#[derive(Clone)]
-pub struct Grid<T>
-where
-    T: Clone + Copy + Default,
-{
-    pub width: usize,
-    pub height: usize,
-    grid: Vec<GridCell<T>>,
+/// `angle` wrapped into `(-PI, PI]`.
+pub fn wrap_angle(angle: f32) -> f32 {
+    let wrapped = angle.rem_euclid(2. * PI);
+    if wrapped > PI {
+        wrapped - 2. * PI
+    } else {
+        wrapped
+    }
 }
 
-impl<T> Index<(usize, usize)> for Grid<T>
-where
-    T: Clone + Copy + Default,
-{
-    type Output = GridCell<T>;
-    fn index(&self, (i, j): (usize, usize)) -> &Self::Output {
-        let ind = i * self.height + j;
-        &self.grid[ind]
-    }
+/// Random point at least `margin` inside `bounds`, on the centre line of axes too short for that.
+pub fn rnd_in_bounds(rng: &mut impl Rng, bounds: (Vec2, Vec2), margin: f32) -> Vec2 {
+    let mut axis = |min: f32, max: f32| {
+        if min + margin < max - margin {
+            rng.gen_range(min + margin..max - margin)
+        } else {
+            0.5 * (min + max)
+        }
+    };
+    Vec2::new(axis(bounds.0.x, bounds.1.x), axis(bounds.0.y, bounds.1.y))
 }
 
-impl<T> IndexMut<(usize, usize)> for Grid<T>
-where
-    T: Clone + Copy + Default,
-{
-    fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut Self::Output {
-        let ind = i * self.height + j;
-        &mut self.grid[ind]
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Link {
+    Force(f32), // force
+    Rigid(f32), // constant length
+    /// Hooke spring with velocity damping along the link.
+    Spring { rest: f32, stiffness: f32, damping: f32 },
+    Rope(f32),  // maximum length, slack when shorter
+    Strut(f32), // minimum length, free when longer
 }
 
-impl<T> Grid<T>
-where
-    T: Clone + Copy + Default,
-{
-    pub fn new(width: usize, height: usize) -> Self {
-        Self {
-            width,
-            height,
-            grid: vec![GridCell::<T>::default(); width * height],
-        }
+impl Link {
+    /// Whether the link moves particles directly instead of accelerating them, only these
+    /// are relaxed repeatedly.
+    pub fn is_positional(&self) -> bool {
+        matches!(self, Link::Rigid(_) | Link::Rope(_) | Link::Strut(_))
     }
 
-    pub fn clear(&mut self) {
-        for cell in self.grid.iter_mut() {
-            cell.clear()
+    /// Length the link tries to keep, `None` for links that only pull.
+    pub fn rest_length(&self) -> Option<f32> {
+        match *self {
+            Link::Force(_) => None,
+            Link::Rigid(length) | Link::Rope(length) | Link::Strut(length) => Some(length),
+            Link::Spring { rest, .. } => Some(rest),
         }
     }
-
-    pub fn push(&mut self, ind: (usize, usize), value: T) {
-        self[ind].push(value);
-    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "StoredConstraint")]
 pub enum Constraint {
     Box(Vec2, Vec2), // Rectangle, bottom-left and top-right corners
-    #[deprecated]
-    Cup(Vec2, Vec2), // U-shape, bottom-left and top-right corners
+    Open(Vec2, Vec2), // No walls, the rectangle only marks the area where particles are spawned
+    // Rectangle whose opposite sides are joined on the axes set in the mask, walls on the others
+    Periodic(Vec2, Vec2, BVec2),
+    Container(Vec2, Vec2, Walls), // Rectangle with only some of its walls
+}
+
+/// Sides of a [`Constraint::Container`] that have a wall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Walls {
+    pub left: bool,
+    pub right: bool,
+    pub bottom: bool,
+    pub top: bool,
+}
+
+impl Walls {
+    pub const ALL: Walls = Walls { left: true, right: true, bottom: true, top: true };
+    pub const NONE: Walls = Walls { left: false, right: false, bottom: false, top: false };
+    pub const OPEN_TOP: Walls = Walls { top: false, ..Walls::ALL };
+    pub const FLOOR: Walls = Walls { bottom: true, ..Walls::NONE };
+}
+
+/// Constraints as they may appear in scene files, including removed variants.
+#[derive(Deserialize)]
+enum StoredConstraint {
+    Box(Vec2, Vec2),
+    Open(Vec2, Vec2),
+    Periodic(Vec2, Vec2, BVec2),
+    Container(Vec2, Vec2, Walls),
+    Cup(Vec2, Vec2),
+}
+
+impl From<StoredConstraint> for Constraint {
+    fn from(stored: StoredConstraint) -> Self {
+        match stored {
+            StoredConstraint::Box(bl, tr) => Constraint::Box(bl, tr),
+            StoredConstraint::Open(bl, tr) => Constraint::Open(bl, tr),
+            StoredConstraint::Periodic(bl, tr, axes) => Constraint::Periodic(bl, tr, axes),
+            StoredConstraint::Container(bl, tr, walls) => Constraint::Container(bl, tr, walls),
+            StoredConstraint::Cup(bl, tr) => Constraint::Container(bl, tr, Walls::OPEN_TOP),
+        }
+    }
 }
 
 impl Constraint {
     pub const fn bounds(&self) -> (Vec2, Vec2) {
-        match self {
-            &Constraint::Box(bl, tr) => (bl, tr),
-            &Constraint::Cup(bl, tr) => (bl, tr),
+        match *self {
+            Constraint::Box(bl, tr) => (bl, tr),
+            Constraint::Open(bl, tr) => (bl, tr),
+            Constraint::Periodic(bl, tr, _) => (bl, tr),
+            Constraint::Container(bl, tr, _) => (bl, tr),
+        }
+    }
+
+    /// Axes on which particles leaving one side reappear on the other.
+    pub fn periodic(&self) -> BVec2 {
+        match *self {
+            Constraint::Periodic(_, _, axes) => axes,
+            _ => BVec2::FALSE,
         }
     }
+
+    /// Shortest vector from `from` to `to`, across the seams of periodic axes.
+    pub fn delta(&self, from: Vec2, to: Vec2) -> Vec2 {
+        to - from + self.image_shift(from, to)
+    }
+
+    /// Shift that moves `to` to its periodic image closest to `from`, zero without periodic axes.
+    pub fn image_shift(&self, from: Vec2, to: Vec2) -> Vec2 {
+        let periodic = self.periodic();
+        if !periodic.any() {
+            return Vec2::ZERO;
+        }
+        let (bl, tr) = self.bounds();
+        let size = tr - bl;
+        let images = -((to - from) / size).round();
+        Vec2::select(periodic, images * size, Vec2::ZERO)
+    }
+}
+
+/// Calls `f` with `p2` moved to its periodic image closest to `p1`, so that distances and
+/// directions between them are measured across the seams.
+fn nearest_image<R>(constraint: Constraint, p1: &mut Particle, p2: &mut Particle, f: impl FnOnce(&mut Particle, &mut Particle) -> R) -> R {
+    let shift = constraint.image_shift(p1.pos, p2.pos);
+    if shift == Vec2::ZERO {
+        return f(p1, p2);
+    }
+    p2.pos += shift;
+    p2.pos_old += shift;
+    let result = f(p1, p2);
+    p2.pos -= shift;
+    p2.pos_old -= shift;
+    result
 }
@@ -0,0 +1,85 @@
+use std::f32::consts::PI;
+
+mod common;
+
+use common::{boxed, chain};
+use glam::vec2;
+use verlet_integration::{Angle, Particle, ParticleMode, SceneFormat, Simulation};
+
+/// A horizontal [`chain`] clamped at the left end by two pinned particles.
+fn beam(stiffness: Option<f32>) -> Simulation {
+    let mut simulation = chain(6, 0.5);
+    simulation.set_mode(0, ParticleMode::Pinned);
+    simulation.set_mode(1, ParticleMode::Pinned);
+    if let Some(stiffness) = stiffness {
+        for i in 1..5 {
+            simulation.add_angle(i - 1, i, i + 1, stiffness);
+        }
+    }
+    for _ in 0..300 {
+        simulation.solve(0.01);
+    }
+    simulation
+}
+
+#[test]
+fn angle_constraints_keep_beams_from_folding() {
+    let limp = beam(None);
+    let stiff = beam(Some(1.));
+    assert!(limp.particles[5].pos.y < 4., "a beam without angles should hang down");
+    assert!(stiff.particles[5].pos.y > 4.8, "the stiff beam sags to {}", stiff.particles[5].pos.y);
+    for a in stiff.angles.iter() {
+        assert!((a.angle(&stiff.particles).abs() - PI).abs() < 0.05);
+    }
+}
+
+#[test]
+fn hinge_limits_stop_the_swing() {
+    // the arm to `k` starts horizontal, a quarter turn from the pinned arm pointing down
+    let particles = [Particle::null().place(vec2(0., 4.)), Particle::null().place(vec2(0., 5.)), Particle::null().place(vec2(1., 5.))];
+    let mut simulation = boxed(&particles);
+    simulation.set_mode(0, ParticleMode::Pinned);
+    simulation.set_mode(1, ParticleMode::Pinned);
+    simulation.add_rib(1, 2, 1.);
+    simulation.angles.push(Angle::new(0, 1, 2, 0., 0.).with_limits(1., 2.));
+    assert!((simulation.angles[0].angle(&simulation.particles) - PI / 2.).abs() < 1e-5);
+    for _ in 0..500 {
+        simulation.solve(0.01);
+    }
+    let angle = simulation.angles[0].angle(&simulation.particles);
+    assert!((angle - 1.).abs() < 0.02, "hinge stopped at {angle}");
+    assert!((simulation.particles[2].pos.distance(vec2(0., 5.)) - 1.).abs() < 0.01);
+}
+
+#[test]
+#[should_panic(expected = "invalid angle limits")]
+fn inverted_limits_are_rejected() {
+    Angle::new(0, 1, 2, 0., 0.).with_limits(2., 1.);
+}
+
+#[test]
+#[should_panic(expected = "invalid angle limits")]
+fn nan_limits_are_rejected() {
+    Angle::new(0, 1, 2, 0., 0.).with_limits(f32::NAN, 1.);
+}
+
+#[test]
+#[should_panic(expected = "invalid angle limits")]
+fn limits_beyond_a_half_turn_are_rejected() {
+    Angle::new(0, 1, 2, 0., 0.).with_limits(-4., 1.);
+}
+
+#[test]
+fn scenes_with_invalid_limits_are_rejected_and_never_panic_the_solver() {
+    let particles = [Particle::null().place(vec2(0., 4.)), Particle::null().place(vec2(0., 5.)), Particle::null().place(vec2(1., 5.))];
+    for limits in [(2., 1.), (f32::NAN, 1.), (-1., 4.)] {
+        let mut simulation = boxed(&particles);
+        simulation.angles.push(Angle { limits: Some(limits), ..Angle::new(0, 1, 2, 0., 0.) });
+        // binary, JSON would write NaN as null
+        let mut data = Vec::new();
+        simulation.write_scene(&mut data, SceneFormat::Binary).unwrap();
+        let error = Simulation::read_scene(data.as_slice()).err().expect("the scene should be rejected");
+        assert!(error.to_string().contains("invalid limits"), "{error}");
+        simulation.solve(0.01);
+    }
+}
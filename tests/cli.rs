@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::process::{Command, Output};
+
+fn cli(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_verlet-cli"))
+        .args(args)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap()
+}
+
+fn error(args: &[&str]) -> String {
+    let output = cli(args);
+    assert!(!output.status.success(), "{args:?} should be rejected");
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn dumps_the_chosen_frames_as_csv() {
+    let output = cli(&["--particles", "5", "--seed", "1", "--frames", "4", "--dump-frames", "2,4"]);
+    assert!(output.status.success());
+    let csv = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "frame,slot,generation,x,y");
+    assert_eq!(lines.len(), 1 + 2 * 5);
+    assert!(lines[1].starts_with("2,") && lines[10].starts_with("4,"));
+}
+
+/// Positions by particle slot of the dumped CSV rows, one map per frame.
+fn frames(csv: &str) -> Vec<HashMap<u32, (f32, f32)>> {
+    let mut frames: Vec<HashMap<_, _>> = Vec::new();
+    for row in csv.lines().skip(1).map(|line| line.split(',').collect::<Vec<_>>()) {
+        let frame: usize = row[0].parse().unwrap();
+        frames.resize_with(frames.len().max(frame + 1), HashMap::new);
+        frames[frame].insert(row[1].parse().unwrap(), (row[3].parse().unwrap(), row[4].parse().unwrap()));
+    }
+    frames
+}
+
+#[test]
+fn dumps_follow_particles_that_are_sorted_or_removed() {
+    let args = ["--particles", "200", "--seed", "3", "--bounds", "-5,0,5,5", "--walls", "left,right", "--kill-plane", "-0.5"];
+    let frames_of_one_step = ["--sub-ticks", "1", "--dt", "0.01", "--frames", "200", "--dump-every", "1"];
+    let output = cli(&[&args[..], &frames_of_one_step, &["--sort-interval", "1"]].concat());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let frames = frames(&String::from_utf8(output.stdout).unwrap());
+    assert!(frames.last().unwrap().len() < 200, "no particle fell below the kill plane");
+    for (frame, pair) in frames.windows(2).enumerate() {
+        for (slot, &(x, y)) in &pair[1] {
+            let (x0, y0) = pair[0][slot];
+            assert!((x - x0).hypot(y - y0) < 0.5, "particle {slot} jumped from {x0},{y0} to {x},{y} after frame {frame}");
+        }
+    }
+}
+
+#[test]
+fn particles_spawn_in_boxes_below_the_origin() {
+    for bounds in ["-1,-1,1,1", "0,-5,3,-1"] {
+        let output = cli(&["--bounds", bounds, "--particles", "10", "--frames", "1"]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 11);
+    }
+}
+
+#[test]
+fn invalid_arguments_are_rejected() {
+    assert!(error(&["--bounds", "1,0,0,1"]).contains("X0 < X1"));
+    assert!(error(&["--bounds", "0,0,0.3,3", "--particles", "1"]).contains("to spawn particles"));
+    assert!(error(&["--open", "--walls", "left"]).contains("exclude each other"));
+    assert!(error(&["--format", "xml"]).contains("unknown format"));
+    assert!(error(&["--frames"]).contains("missing value for --frames"));
+    assert!(error(&["--particles", "4294967297"]).contains("must not exceed"));
+    assert!(error(&["--frobnicate"]).contains("unknown argument"));
+}
+
+#[test]
+fn spawn_options_conflict_with_a_scene() {
+    let message = error(&["--scene", "scene.json", "--particles", "10", "--bounds", "0,0,5,5", "--frames", "1"]);
+    assert!(message.contains("--particles, --bounds only apply to spawned particles"), "{message}");
+    assert!(error(&["--scene", "scene.json", "--material", "sand"]).contains("--material"));
+}
+
+#[test]
+fn help_prints_the_usage() {
+    let output = cli(&["--help"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with("Usage: verlet-cli"));
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use common::{dropped, launched, run, world};
+use glam::{vec2, Vec2};
+use verlet_integration::solver::{Parameters, PARTICLE_SIZE};
+use verlet_integration::{Collider, Constraint, Particle, SceneFormat, Simulation};
+
+#[test]
+fn concave_polygon_distances() {
+    // a U-shaped cup, the notch is outside of the polygon
+    let cup = Collider::polygon([vec2(0., 0.), vec2(3., 0.), vec2(3., 3.), vec2(2., 3.), vec2(2., 1.), vec2(1., 1.), vec2(1., 3.), vec2(0., 3.)]);
+    let (d, n) = cup.signed_distance(vec2(1.5, 2.));
+    assert!((d - 0.5).abs() < 1e-6);
+    assert!(n == vec2(1., 0.) || n == vec2(-1., 0.));
+    let (d, n) = cup.signed_distance(vec2(0.5, 0.4));
+    assert!((d + 0.4).abs() < 1e-6);
+    assert_eq!(n, vec2(0., -1.));
+    let (d, n) = cup.signed_distance(vec2(1.5, 0.8));
+    assert!((d + 0.2).abs() < 1e-6, "distance to the notch floor {d}");
+    assert_eq!(n, vec2(0., 1.));
+}
+
+#[test]
+fn particles_rest_on_circles_and_stay_in_bowls() {
+    let mut on_circle = dropped(vec![Collider::circle(vec2(0., 0.), 2.)], vec2(0., 4.));
+    run(&mut on_circle, 100);
+    let pos = on_circle.particles[0].pos;
+    assert!((pos.length() - 2. - PARTICLE_SIZE).abs() < 0.01, "resting at {pos}");
+
+    let mut in_bowl = dropped(vec![Collider::circle(vec2(0., 0.), 2.).with_inside()], vec2(1., 0.));
+    run(&mut in_bowl, 100);
+    let pos = in_bowl.particles[0].pos;
+    assert!(pos.length() <= 2. - PARTICLE_SIZE + 1e-4 && pos.y < -1.5, "resting at {pos}");
+}
+
+#[test]
+fn particles_slide_down_ramps_and_fall_into_notches() {
+    let ramp = Collider::polyline([vec2(-5., 5.), vec2(5., 0.)]);
+    let mut simulation = dropped(vec![ramp.clone()], vec2(-4., 5.));
+    let mut last_x = -4.;
+    for _ in 0..10 {
+        run(&mut simulation, 5);
+        let pos = simulation.particles[0].pos;
+        assert!(ramp.signed_distance(pos).0 >= PARTICLE_SIZE - 1e-3, "below the ramp at {pos}");
+        assert!(pos.x >= last_x);
+        last_x = pos.x;
+    }
+    assert!(last_x > -2.);
+
+    let cup = Collider::polygon([vec2(0., 0.), vec2(3., 0.), vec2(3., 3.), vec2(2., 3.), vec2(2., 1.), vec2(1., 1.), vec2(1., 3.), vec2(0., 3.)]);
+    let mut simulation = dropped(vec![cup], vec2(1.6, 4.));
+    run(&mut simulation, 100);
+    let pos = simulation.particles[0].pos;
+    assert!((pos.y - 1. - PARTICLE_SIZE).abs() < 0.01 && pos.x > 1. && pos.x < 2., "resting at {pos}");
+}
+
+#[test]
+fn fast_particles_do_not_tunnel_through_polylines() {
+    let floor = Collider::polyline([vec2(-2., 0.), vec2(0., 0.), vec2(2., 0.5)]);
+    for (from, velocity) in [(vec2(-1., 0.3), vec2(0., -0.8)), (vec2(-1., -0.3), vec2(0., 0.8)), (vec2(1., 1.), vec2(0.2, -1.5))] {
+        let particle = launched(Particle::null(), from, velocity);
+        let mut simulation = world(Constraint::Open(vec2(-6., -3.), vec2(6., 6.)), &[particle])
+            .with_parameters(Parameters { gravity: Vec2::ZERO, ..Parameters::default() })
+            .with_colliders([floor.clone()]);
+        simulation.solve(0.01);
+        let pos = simulation.particles[0].pos;
+        let (distance, normal) = floor.signed_distance(pos);
+        assert!((distance - PARTICLE_SIZE).abs() < 1e-4, "{from}: {distance} from the line at {pos}");
+        assert!(normal.dot(from - pos) > 0., "{from}: pushed through to {pos}");
+    }
+}
+
+#[test]
+fn colliders_are_saved_with_the_scene() {
+    let colliders = vec![Collider::circle(vec2(1., 2.), 3.).with_inside(), Collider::polyline([vec2(0., 0.), vec2(1., 1.)])];
+    let simulation = dropped(colliders.clone(), vec2(0., 0.));
+    for format in [SceneFormat::Json, SceneFormat::Binary] {
+        let mut data = Vec::new();
+        simulation.write_scene(&mut data, format).unwrap();
+        assert_eq!(Simulation::read_scene(data.as_slice()).unwrap().colliders, colliders);
+    }
+
+    let invalid = dropped(vec![Collider::polygon([vec2(0., 0.), vec2(1., 1.)])], vec2(0., 0.));
+    let mut data = Vec::new();
+    invalid.write_scene(&mut data, SceneFormat::Json).unwrap();
+    assert!(Simulation::read_scene(data.as_slice()).is_err());
+}
+
+#[test]
+fn colliders_are_placed_by_their_transform() {
+    let plank = Collider::polygon([vec2(-2., -0.5), vec2(2., -0.5), vec2(2., 0.5), vec2(-2., 0.5)])
+        .with_transform(vec2(1., 1.), std::f32::consts::FRAC_PI_2);
+    let (d, n) = plank.signed_distance(vec2(1., 4.));
+    assert!((d - 1.).abs() < 1e-5);
+    assert!(n.distance(vec2(0., 1.)) < 1e-5);
+    let (d, n) = plank.signed_distance(vec2(2.5, 1.));
+    assert!((d - 1.).abs() < 1e-5);
+    assert!(n.distance(vec2(1., 0.)) < 1e-5);
+}
+
+#[test]
+fn pistons_push_and_conveyors_carry_particles() {
+    let floor = || Collider::polygon([vec2(-5., -1.), vec2(5., -1.), vec2(5., 0.), vec2(-5., 0.)]);
+    let resting = |collider: Collider| {
+        let mut simulation = dropped(vec![collider], vec2(0., PARTICLE_SIZE));
+        simulation.particles[0].friction = 0.5;
+        simulation
+    };
+
+    let mut piston = resting(floor().with_velocity(vec2(0., 1.), 0.));
+    run(&mut piston, 20);
+    let pos = piston.particles[0].pos;
+    assert!((piston.colliders[0].position.y - 1.6).abs() < 1e-3);
+    assert!((pos.y - 1.6 - PARTICLE_SIZE).abs() < 0.01, "pushed to {pos}");
+
+    let mut conveyor = resting(floor().with_surface_speed(1.));
+    let mut still = resting(floor());
+    run(&mut conveyor, 20);
+    run(&mut still, 20);
+    assert!(conveyor.particles[0].pos.x > 0.5, "carried to {}", conveyor.particles[0].pos);
+    assert!(still.particles[0].pos.x.abs() < 1e-4);
+    assert_eq!(conveyor.colliders[0].position, Vec2::ZERO);
+}
+
+#[test]
+fn animated_colliders_reach_their_keyframes() {
+    let mut simulation = dropped(vec![Collider::circle(vec2(0., 0.), 0.5)], vec2(3., 3.));
+    let frame_dt = simulation.parameters.frame_dt;
+    simulation.colliders[0].animate_to(vec2(1., 0.), 1., frame_dt);
+    simulation.step_frame();
+    let collider = &simulation.colliders[0];
+    assert!(collider.position.distance(vec2(1., 0.)) < 1e-4 && (collider.rotation - 1.).abs() < 1e-4);
+}
@@ -0,0 +1,52 @@
+//! Fixtures shared by the integration tests, each test file uses only some of them.
+#![allow(dead_code)]
+
+use glam::{vec2, Vec2};
+use verlet_integration::solver::{Parameters, PARTICLE_SIZE};
+use verlet_integration::{Collider, Constraint, Particle, Simulation};
+
+/// `particles` in `constraint`, with grid cells sized for particles of the default size.
+pub fn world(constraint: Constraint, particles: &[Particle]) -> Simulation {
+    Simulation::new(constraint, 2. * PARTICLE_SIZE, particles, &[])
+}
+
+/// [`world`] in the 20 x 10 box most tests use.
+pub fn boxed(particles: &[Particle]) -> Simulation {
+    world(Constraint::Box(vec2(-10., 0.), vec2(10., 10.)), particles)
+}
+
+/// `n` particles `spacing` apart on a horizontal line starting at `start`.
+pub fn row(n: usize, start: Vec2, spacing: f32) -> Vec<Particle> {
+    (0..n).map(|i| Particle::null().place(start + vec2(spacing * i as f32, 0.))).collect()
+}
+
+/// A [`row`] of `n` particles at height 5 of the [`boxed`] world, each linked to the next by
+/// a rib of length `spacing`.
+pub fn chain(n: usize, spacing: f32) -> Simulation {
+    let mut simulation = boxed(&row(n, vec2(0., 5.), spacing));
+    for i in 1..n {
+        simulation.add_rib(i - 1, i, spacing);
+    }
+    simulation
+}
+
+/// `particle` at `pos`, moving by `velocity` per step.
+pub fn launched(particle: Particle, pos: Vec2, velocity: Vec2) -> Particle {
+    let mut particle = particle.place(pos);
+    particle.pos_old = pos - velocity;
+    particle
+}
+
+/// A single particle at `pos` about to fall onto `colliders`, in an open world.
+pub fn dropped(colliders: Vec<Collider>, pos: Vec2) -> Simulation {
+    let parameters = Parameters { gravity: vec2(0., -10.), damping: 0.5, ..Parameters::default() };
+    world(Constraint::Open(vec2(-6., -3.), vec2(6., 6.)), &[Particle::null().place(pos)])
+        .with_parameters(parameters)
+        .with_colliders(colliders)
+}
+
+pub fn run(simulation: &mut Simulation, frames: usize) {
+    for _ in 0..frames {
+        simulation.step_frame();
+    }
+}
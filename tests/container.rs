@@ -0,0 +1,102 @@
+mod common;
+
+use common::{launched, world};
+use glam::vec2;
+use verlet_integration::solver::{Parameters, Walls, PARTICLE_SIZE};
+use verlet_integration::{Connection, Constraint, Event, Link, Particle, SceneFormat, Simulation};
+
+fn container(walls: Walls, particles: &[Particle]) -> Simulation {
+    let parameters = Parameters { gravity: vec2(0., -10.), damping: 0., ..Parameters::default() };
+    world(Constraint::Container(vec2(0., 0.), vec2(2., 2.), walls), particles).with_parameters(parameters)
+}
+
+#[test]
+fn containers_only_have_the_chosen_walls() {
+    let thrown = launched(Particle::null(), vec2(1., 1.8), vec2(0., 0.05));
+    let mut open_top = container(Walls::OPEN_TOP, &[thrown]);
+    let mut closed = container(Walls::ALL, &[thrown]);
+    for _ in 0..5 {
+        open_top.solve(0.01);
+        closed.solve(0.01);
+    }
+    assert!(open_top.particles[0].pos.y > 2., "{}", open_top.particles[0].pos);
+    assert!(closed.particles[0].pos.y <= 2. - PARTICLE_SIZE, "{}", closed.particles[0].pos);
+
+    let sliding = launched(Particle::null(), vec2(1.9, 0.5), vec2(0.02, 0.));
+    let mut floor = container(Walls::FLOOR, &[sliding]);
+    for _ in 0..20 {
+        floor.solve(0.01);
+    }
+    assert!(floor.particles[0].pos.x > 2.);
+}
+
+#[test]
+fn particles_below_the_kill_plane_are_removed() {
+    let particles = [Particle::null().place(vec2(1., 0.5)), Particle::null().place(vec2(1.9, 1.)), Particle::null().place(vec2(1.5, 1.))];
+    let mut simulation = container(Walls::FLOOR, &particles).with_kill_plane(-1.);
+    simulation.add_rib(1, 2, 0.4);
+    simulation.particles[1].pos_old.x -= 0.05;
+    simulation.particles[2].pos_old.x -= 0.05;
+    let overflowing = [simulation.id_of(1).unwrap(), simulation.id_of(2).unwrap()];
+
+    let mut removed = Vec::new();
+    for _ in 0..200 {
+        simulation.solve(0.01);
+        removed.extend(simulation.events.iter().filter_map(|event| match event {
+            Event::ParticleRemoved(id) => Some(*id),
+            _ => None,
+        }));
+    }
+    removed.sort();
+    assert_eq!(removed, overflowing);
+    assert_eq!(simulation.particles.len(), 1);
+    assert!(simulation.connections.is_empty());
+    assert_eq!(simulation.index_of(overflowing[0]), None);
+}
+
+#[test]
+fn links_broken_in_the_step_of_a_removal_name_the_right_particles() {
+    // the first particle falls below the kill plane in the same step the overstretched link breaks
+    let particles = [launched(Particle::null(), vec2(3., -0.99), vec2(0., -0.05)), Particle::null().place(vec2(0.5, 1.)), Particle::null().place(vec2(1.5, 1.))];
+    let mut simulation = container(Walls::FLOOR, &particles).with_kill_plane(-1.);
+    simulation.connections.push(Connection::new(1, 2, Link::Rigid(0.4)).with_strength(0.1, 100.));
+    let linked = [simulation.id_of(1).unwrap(), simulation.id_of(2).unwrap()];
+
+    simulation.solve(0.01);
+
+    assert_eq!(simulation.particles.len(), 2);
+    let broken: Vec<_> = simulation.events.iter().filter_map(|event| match event {
+        Event::LinkBroken(_, ends) => Some(*ends),
+        _ => None,
+    }).collect();
+    assert_eq!(broken, [linked]);
+    assert_eq!(linked.map(|id| simulation.index_of(id)), [Some(0), Some(1)]);
+}
+
+#[test]
+fn cup_scenes_load_as_open_top_containers() {
+    let simulation = world(Constraint::Box(vec2(-1., 0.), vec2(1., 3.)), &[]).with_kill_plane(-2.);
+    let mut data = Vec::new();
+    simulation.write_scene(&mut data, SceneFormat::Json).unwrap();
+    let json = String::from_utf8(data).unwrap().replace("\"Box\"", "\"Cup\"");
+    let loaded = Simulation::read_scene(json.as_bytes()).unwrap();
+    assert!(matches!(loaded.constraint, Constraint::Container(bl, tr, Walls::OPEN_TOP) if bl == vec2(-1., 0.) && tr == vec2(1., 3.)));
+    assert_eq!(loaded.kill_plane, Some(-2.));
+}
+
+#[test]
+fn packed_containers_do_not_leak_through_closed_walls() {
+    let particles: Vec<_> = (0..1000)
+        .map(|i| Particle::null().place(vec2(0.1 + 0.2 * (i % 20) as f32, 0.1 + 0.2 * (i / 20) as f32)))
+        .collect();
+    let parameters = Parameters { gravity: vec2(0., -5.), ..Parameters::default() };
+    let mut simulation = world(Constraint::Container(vec2(0., 0.), vec2(4., 40.), Walls::OPEN_TOP), &particles)
+        .with_parameters(parameters)
+        .with_seed(1);
+    for _ in 0..500 {
+        simulation.solve(0.01);
+    }
+    for p in &simulation.particles {
+        assert!(p.pos.x >= 0. && p.pos.x <= 4. && p.pos.y >= 0., "leaked to {}", p.pos);
+    }
+}
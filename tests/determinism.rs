@@ -0,0 +1,72 @@
+mod common;
+
+use common::boxed;
+use verlet_integration::solver::BroadPhase;
+use verlet_integration::{SceneFormat, Simulation};
+
+fn run(seed: u64, broad_phase: BroadPhase, threads: usize) -> u64 {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    pool.install(|| {
+        let mut simulation = boxed(&[])
+        .with_broad_phase(broad_phase)
+        .with_seed(seed);
+        simulation.change_number(500);
+        simulation.add_square(1.);
+        for _ in 0..100 {
+            simulation.solve(0.01);
+        }
+        simulation.state_hash()
+    })
+}
+
+#[test]
+fn same_seed_gives_identical_states() {
+    for broad_phase in [BroadPhase::Grid, BroadPhase::SpatialHash] {
+        let reference = run(42, broad_phase, 1);
+        assert_eq!(run(42, broad_phase, 1), reference);
+        assert_eq!(run(42, broad_phase, 4), reference, "result depends on the thread count");
+        assert_ne!(run(43, broad_phase, 1), reference);
+    }
+}
+
+#[test]
+fn saved_scene_continues_identically() {
+    let mut simulation = boxed(&[])
+    .with_seed(7);
+    simulation.change_number(200);
+    for _ in 0..50 {
+        simulation.solve(0.01);
+    }
+
+    for format in [SceneFormat::Json, SceneFormat::Binary] {
+        let mut data = Vec::new();
+        simulation.write_scene(&mut data, format).unwrap();
+        let mut loaded = Simulation::read_scene(data.as_slice()).unwrap();
+        assert_eq!(loaded.state_hash(), simulation.state_hash());
+
+        let mut original = simulation.clone();
+        for _ in 0..50 {
+            original.solve(0.01);
+            loaded.solve(0.01);
+        }
+        original.change_number(300);
+        loaded.change_number(300);
+        assert_eq!(loaded.state_hash(), original.state_hash(), "{format:?} scene diverged");
+    }
+}
+
+#[test]
+fn hash_covers_the_random_generator_and_step_count() {
+    let simulation = boxed(&[]).with_seed(1);
+    let reseeded = simulation.clone().with_seed(2);
+    assert_ne!(reseeded.state_hash(), simulation.state_hash());
+
+    let mut drawn = simulation.clone();
+    drawn.change_number(1);
+    drawn.change_number(0);
+    assert_ne!(drawn.state_hash(), simulation.state_hash(), "the generator advanced");
+
+    let mut stepped = simulation.clone();
+    stepped.solve(0.01);
+    assert_ne!(stepped.state_hash(), simulation.state_hash(), "the step count advanced");
+}
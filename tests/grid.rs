@@ -0,0 +1,164 @@
+mod common;
+
+use common::world;
+use glam::vec2;
+use verlet_integration::grid::Grid;
+use verlet_integration::solver::{BroadPhase, MAX_GRID_LEVEL, PARTICLE_SIZE};
+use verlet_integration::{Constraint, Particle, SceneFormat, Simulation};
+
+#[test]
+fn grid_keeps_every_element_of_a_crowded_cell() {
+    let mut grid = Grid::<usize>::new(4, 4);
+    let items: Vec<_> = (0..100).map(|i| ((1 + i % 2, 1), i)).collect();
+    grid.fill(items);
+
+    assert_eq!(grid.len(), 100);
+    assert_eq!(grid[(1, 1)].len(), 50);
+    assert_eq!(grid[(2, 1)].len(), 50);
+    assert!(grid[(1, 1)].iter().all(|i| i % 2 == 0));
+    assert!(grid[(0, 0)].is_empty() && grid[(3, 3)].is_empty());
+}
+
+#[test]
+fn all_pairs_in_one_cell_are_resolved() {
+    // a cell large enough to hold a whole pile of particles
+    let cell_size = 20. * PARTICLE_SIZE;
+    let particles: Vec<_> = (0..16)
+        .map(|i| {
+            let offset = vec2((i % 4) as f32, (i / 4) as f32) * 0.01;
+            Particle::null().place(vec2(1., 1.) + offset)
+        })
+        .collect();
+    let mut simulation = Simulation::new(
+        Constraint::Box(vec2(0., 0.), vec2(2., 2.)),
+        cell_size,
+        &particles,
+        &[],
+    );
+
+    for _ in 0..2000 {
+        simulation.solve(0.01);
+    }
+
+    let particles = &simulation.particles;
+    for i in 0..particles.len() {
+        for j in i + 1..particles.len() {
+            let distance = particles[i].pos.distance(particles[j].pos);
+            let min = particles[i].radius + particles[j].radius;
+            assert!(
+                distance > 0.9 * min,
+                "particles {i} and {j} overlap: distance {distance}, expected at least {min}"
+            );
+        }
+    }
+}
+
+#[test]
+fn large_particles_collide_with_small_ones() {
+    let cell_size = 2. * PARTICLE_SIZE;
+    // a boulder spanning many cells of the finest level, touched by sand grains all around
+    let center = vec2(0., 5.);
+    let mut particles = vec![Particle::new(2., 100., center, 0)];
+    particles.extend((0..12).map(|i| {
+        let angle = i as f32 * std::f32::consts::PI / 6.;
+        Particle::null().place(center + 2.05 * vec2(angle.cos(), angle.sin()))
+    }));
+    let mut simulation = Simulation::new(
+        Constraint::Box(vec2(-10., 0.), vec2(10., 10.)),
+        cell_size,
+        &particles,
+        &[],
+    );
+
+    // zero time step: only the collision response moves particles
+    simulation.solve(0.);
+
+    let boulder = simulation.particles[0];
+    for (i, p) in simulation.particles.iter().enumerate().skip(1) {
+        let distance = boulder.pos.distance(p.pos);
+        let min = boulder.radius + p.radius;
+        assert!(
+            distance > min - 0.01,
+            "particle {i} still overlaps the boulder: distance {distance}, expected at least {min}"
+        );
+    }
+}
+
+#[test]
+fn grid_levels_follow_the_constraint_and_cell_size() {
+    let mut simulation = world(Constraint::Box(vec2(0., 0.), vec2(2., 2.)), &[Particle::null().place(vec2(1., 1.))]);
+    simulation.solve(0.01);
+
+    simulation.constraint = Constraint::Box(vec2(-10., 0.), vec2(10., 20.));
+    simulation.cell_size = 4. * PARTICLE_SIZE;
+    simulation.solve(0.01);
+    assert_eq!(simulation.grids[0].bounds, simulation.constraint.bounds());
+    assert_eq!(simulation.grids[0].cell_size, simulation.cell_size);
+
+    simulation.broad_phase = BroadPhase::SpatialHash;
+    simulation.solve(0.01);
+    simulation.cell_size = 2. * PARTICLE_SIZE;
+    simulation.solve(0.01);
+    assert_eq!(simulation.hashes[0].cell_size, simulation.cell_size);
+}
+
+#[test]
+fn spatial_hash_works_outside_of_the_bounds() {
+    let cell_size = 2. * PARTICLE_SIZE;
+    let far = vec2(1000., -500.);
+    let particles = [Particle::null().place(far), Particle::null().place(far + vec2(0.05, 0.)), Particle::null().place(-far)];
+    let mut simulation = Simulation::new(
+        Constraint::Open(vec2(-10., -10.), vec2(10., 10.)),
+        cell_size,
+        &particles,
+        &[],
+    )
+    .with_broad_phase(BroadPhase::SpatialHash);
+
+    simulation.solve(0.);
+
+    let p = &simulation.particles;
+    let min = p[0].radius + p[1].radius;
+    assert!(p[0].pos.distance(p[1].pos) > min - 1e-4, "far away particles were not separated");
+    assert!(p[2].pos.distance(-far) < 1e-3, "an isolated particle was moved");
+}
+
+#[test]
+fn spatial_hash_handles_particles_near_the_float_limits() {
+    let cell_size = 2. * PARTICLE_SIZE;
+    let far = vec2(1e9, -1e9);
+    let particles = [
+        Particle::null().place(far),
+        Particle::null().place(far + vec2(128., 0.)),
+        Particle::null().place(-far),
+        Particle::null().place(vec2(f32::MAX, f32::MIN)),
+        Particle::null().place(vec2(0., 0.)),
+        Particle::null().place(vec2(0.15, 0.)),
+    ];
+    let mut simulation = Simulation::new(Constraint::Open(vec2(-10., -10.), vec2(10., 10.)), cell_size, &particles, &[])
+        .with_broad_phase(BroadPhase::SpatialHash);
+
+    simulation.solve(0.);
+
+    let p = &simulation.particles;
+    assert!(p[4].pos.distance(p[5].pos) > 2. * PARTICLE_SIZE - 1e-4, "nearby particles were not separated");
+    assert_eq!(p[2].pos, -far);
+}
+
+#[test]
+fn huge_particles_share_the_coarsest_level() {
+    let huge = Particle { radius: 1e30, ..Particle::null() }.place(vec2(0., 0.));
+    for broad_phase in [BroadPhase::Grid, BroadPhase::SpatialHash] {
+        let mut simulation = world(Constraint::Box(vec2(-10., -10.), vec2(10., 10.)), &[])
+            .with_broad_phase(broad_phase);
+        simulation.add_particle(huge);
+        simulation.add_particle(Particle::null().place(vec2(3., 3.)));
+        assert_eq!(simulation.grid_level(huge.radius), MAX_GRID_LEVEL);
+        simulation.solve(0.01);
+
+        let mut data = Vec::new();
+        simulation.write_scene(&mut data, SceneFormat::Binary).unwrap();
+        let error = Simulation::read_scene(data.as_slice()).err().expect("the scene should be rejected");
+        assert!(error.to_string().contains("too large for the cell size"), "{error}");
+    }
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{boxed, row};
+use glam::vec2;
+use verlet_integration::{Particle, Simulation};
+
+#[test]
+fn ids_follow_particles_across_removal() {
+    let mut simulation = boxed(&row(5, vec2(0., 5.), 1.));
+    let ids: Vec<_> = (0..5).map(|i| simulation.id_of(i).unwrap()).collect();
+
+    simulation.remove_particles([0, 2]);
+    assert_eq!(simulation.index_of(ids[0]), None);
+    assert_eq!(simulation.index_of(ids[2]), None);
+    for (k, &id) in [1, 3, 4].iter().enumerate() {
+        assert_eq!(simulation.index_of(ids[id]), Some(k));
+        assert_eq!(simulation.particle(ids[id]).unwrap().pos.x, id as f32);
+    }
+
+    simulation.particle_mut(ids[4]).unwrap().pos.y = 7.;
+    assert_eq!(simulation.particles[2].pos.y, 7.);
+}
+
+#[test]
+fn stale_ids_do_not_resolve_after_slot_reuse() {
+    let mut simulation = boxed(&row(3, vec2(0., 5.), 1.));
+    let removed = simulation.id_of(1).unwrap();
+    simulation.remove_particles([1]);
+
+    let added = simulation.add_particle(Particle::null().place(vec2(9., 5.)));
+    assert_eq!(added.slot, removed.slot);
+    assert_ne!(added, removed);
+    assert_eq!(simulation.index_of(removed), None);
+    assert_eq!(simulation.index_of(added), Some(2));
+
+    // particles pushed directly get their ids on the next step
+    simulation.particles.push(Particle::null().place(vec2(-9., 5.)));
+    simulation.solve(0.01);
+    assert!(simulation.id_of(3).is_some());
+}
+
+#[test]
+fn ids_survive_save_and_load() {
+    let mut simulation = boxed(&row(4, vec2(0., 5.), 1.));
+    simulation.remove_particles([1]);
+    let ids: Vec<_> = (0..3).map(|i| simulation.id_of(i).unwrap()).collect();
+    simulation.particles.push(Particle::null().place(vec2(-9., 5.)));
+
+    let mut data = Vec::new();
+    simulation.write_scene(&mut data, verlet_integration::SceneFormat::Binary).unwrap();
+    let loaded = Simulation::read_scene(data.as_slice()).unwrap();
+    for (i, &id) in ids.iter().enumerate() {
+        assert_eq!(loaded.index_of(id), Some(i));
+    }
+    assert!(loaded.id_of(3).is_some());
+
+    let mut json = Vec::new();
+    simulation.write_scene(&mut json, verlet_integration::SceneFormat::Json).unwrap();
+    let loaded = Simulation::read_scene(json.as_slice()).unwrap();
+    assert_eq!(loaded.index_of(ids[2]), Some(2));
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use common::{boxed, row};
+use glam::vec2;
+use verlet_integration::solver::{ConstraintOrder, Parameters};
+use verlet_integration::{Connection, Event, Link, Particle, ParticleMode, Simulation};
+
+fn pair(connection: Connection) -> Simulation {
+    let mut simulation = boxed(&row(2, vec2(0., 5.), 1.)).with_parameters(Parameters { gravity: vec2(0., 0.), ..Parameters::default() });
+    simulation.connections.push(connection);
+    simulation.set_mode(0, ParticleMode::Kinematic);
+    simulation
+}
+
+/// Drives the first particle left by `speed` per step, returns the step in which the link broke.
+fn pull(simulation: &mut Simulation, speed: f32, steps: usize) -> Option<usize> {
+    for step in 0..steps {
+        let pos = simulation.particles[0].pos - vec2(speed, 0.);
+        simulation.drive(0, pos);
+        simulation.solve(0.01);
+        if let Some(Event::LinkBroken(connection, ends)) = simulation.events.first() {
+            assert_eq!((connection.i, connection.j), (0, 1));
+            assert_eq!(ends.map(|id| simulation.ids.index(id)), [Some(0), Some(1)]);
+            assert!(simulation.connections.is_empty());
+            return Some(step);
+        }
+    }
+    None
+}
+
+#[test]
+fn links_break_when_overstretched() {
+    let link = Connection::new(0, 1, Link::Rigid(1.)).with_strength(0.1, 100.);
+    assert_eq!(pull(&mut pair(link), 0.05, 100), None);
+    assert_eq!(pull(&mut pair(link), 0.2, 100), Some(0));
+    // unbreakable links hold whatever the load
+    assert_eq!(pull(&mut pair(Connection::new(0, 1, Link::Rigid(1.))), 0.5, 100), None);
+}
+
+#[test]
+fn sustained_stress_breaks_links() {
+    // a heavy particle hanging from a pinned one stretches the link by about 5% every step
+    let hanging = |max_strain, max_stress| {
+        let particles = [Particle::null().place(vec2(0., 5.)), Particle::null().place(vec2(0., 4.))];
+        let link = Connection::new(0, 1, Link::Rigid(1.)).with_strength(max_strain, max_stress);
+        let mut simulation = boxed(&particles).with_parameters(Parameters { gravity: vec2(0., -500.), ..Parameters::default() });
+        simulation.connections.push(link);
+        simulation.set_mode(0, ParticleMode::Pinned);
+        (0..100).find(|_| {
+            simulation.solve(0.01);
+            !simulation.events.is_empty()
+        })
+    };
+    let step = hanging(0.1, 2.).expect("the link should break");
+    assert!(step > 10, "broke after {step} steps");
+    assert_eq!(hanging(0.1, 10.), None);
+    assert!(hanging(0.01, 10.).is_some_and(|step| step < 3));
+}
+
+#[test]
+fn connections_of_older_scenes_still_load() {
+    let json = r#"{"version": 3, "constraint": {"Box": [[-10, 0], [10, 10]]}, "cell_size": 0.2,
+        "particles": [{"radius": 0.1, "mass": 1, "pos": [0, 1], "pos_old": [0, 1], "acc": [0, 0], "texture": 0},
+                      {"radius": 0.1, "mass": 1, "pos": [1, 1], "pos_old": [1, 1], "acc": [0, 0], "texture": 0}],
+        "connections": [[0, 1, {"Rigid": 1.0}]]}"#;
+    let simulation = Simulation::read_scene(json.as_bytes()).unwrap();
+    assert_eq!(simulation.connections, [Connection::new(0, 1, Link::Rigid(1.))]);
+}
+
+fn hanging(link: Link) -> Simulation {
+    let particles = [Particle::null().place(vec2(0., 8.)), Particle::null().place(vec2(0., 7.))];
+    let mut simulation = boxed(&particles).with_parameters(Parameters { damping: 0., ..Parameters::default() });
+    simulation.connections.push(Connection::new(0, 1, link));
+    simulation.set_mode(0, ParticleMode::Pinned);
+    simulation
+}
+
+#[test]
+fn damped_springs_settle_at_the_hookean_extension() {
+    // gravity 1 and stiffness 20 give an extension of 0.05 at rest
+    let mut simulation = hanging(Link::Spring { rest: 1., stiffness: 20., damping: 2. });
+    let mut lowest = 8f32;
+    for _ in 0..1000 {
+        simulation.solve(0.01);
+        lowest = lowest.min(simulation.particles[1].pos.y);
+    }
+    let length = 8. - simulation.particles[1].pos.y;
+    assert!((length - 1.05).abs() < 0.005, "spring length {length}");
+    assert!(lowest < 6.95 - 0.01, "the spring should overshoot before settling");
+}
+
+#[test]
+fn ropes_and_struts_only_act_one_way() {
+    // a slack rope lets the particle fall until it is taut
+    let mut rope = hanging(Link::Rope(2.));
+    for _ in 0..300 {
+        rope.solve(0.01);
+    }
+    assert!((8. - rope.particles[1].pos.y - 2.).abs() < 0.01);
+
+    // a strut only keeps the particle from coming closer
+    let mut strut = hanging(Link::Strut(0.5));
+    for _ in 0..300 {
+        strut.solve(0.01);
+    }
+    assert!(strut.particles[1].pos.y < 6.);
+    let mut strut = hanging(Link::Strut(2.));
+    strut.solve(0.01);
+    assert!(8. - strut.particles[1].pos.y >= 2. - 1e-4);
+}
+
+/// Stretch of a pinned chain of 40 ribs with heavy particles hanging at its end.
+fn chain_stretch(iterations: usize, constraint_order: ConstraintOrder, threads: usize) -> (f32, u64) {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    pool.install(|| {
+        let mut simulation = boxed(&row(41, vec2(-5., 9.), 0.25))
+            .with_parameters(Parameters { iterations, constraint_order, ..Parameters::default() })
+            .with_seed(1);
+        simulation.particles[40].mass = 100.;
+        simulation.set_mode(0, ParticleMode::Pinned);
+        for i in 0..40 {
+            simulation.add_rib(i, i + 1, 0.25);
+        }
+        for _ in 0..200 {
+            simulation.solve(0.01);
+        }
+        let length: f32 = (0..40).map(|i| simulation.particles[i].pos.distance(simulation.particles[i + 1].pos)).sum();
+        (length / 10. - 1., simulation.state_hash())
+    })
+}
+
+#[test]
+fn more_iterations_keep_chains_stiff() {
+    for order in ConstraintOrder::ALL {
+        let (loose, _) = chain_stretch(1, order, 1);
+        let (stiff, _) = chain_stretch(20, order, 1);
+        assert!(stiff < loose / 4., "{order}: stretch {stiff} with 20 iterations and {loose} with one");
+    }
+}
+
+#[test]
+fn constraint_orders_are_deterministic() {
+    for order in ConstraintOrder::ALL {
+        assert_eq!(chain_stretch(4, order, 1).1, chain_stretch(4, order, 4).1, "{order} depends on the thread count");
+    }
+}
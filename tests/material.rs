@@ -0,0 +1,67 @@
+mod common;
+
+use common::{boxed, world};
+use glam::vec2;
+use verlet_integration::solver::Parameters;
+use verlet_integration::{Constraint, MaterialRegistry, SceneFormat, Simulation};
+
+#[test]
+fn particles_take_their_properties_from_the_material() {
+    let materials = MaterialRegistry::default();
+    let sand = materials.id("sand").unwrap();
+    let metal = materials.id("metal").unwrap();
+    let grain = materials.particle(sand, vec2(1., 2.));
+    let ball = materials.particle(metal, vec2(1., 2.));
+    assert_eq!((grain.material, ball.material), (sand, metal));
+    assert_eq!(grain.pos, vec2(1., 2.));
+    assert_eq!(grain.friction, materials.get(sand).unwrap().friction);
+    assert!((ball.mass / grain.mass - 10.).abs() < 1e-4);
+    assert!(materials.visible().any(|(_, m)| m.name == "clay"));
+}
+
+#[test]
+fn invalid_registries_are_rejected() {
+    let twice = r#"{"materials": [{"name": "a", "density": 1, "radius": 0.1}, {"name": "a", "density": 2, "radius": 0.1}]}"#;
+    assert!(MaterialRegistry::from_json(twice).is_err());
+    let massless = r#"{"materials": [{"name": "a", "density": 0, "radius": 0.1}]}"#;
+    assert!(MaterialRegistry::from_json(massless).is_err());
+    // serde_json reads numbers too large for f32 as infinity
+    let infinite = r#"{"materials": [{"name": "a", "density": 1e39, "radius": 0.1}]}"#;
+    assert!(MaterialRegistry::from_json(infinite).is_err());
+    assert!(MaterialRegistry::from_json(r#"{"materials": []}"#).is_err());
+}
+
+#[test]
+fn custom_materials_are_saved_with_the_scene() {
+    let json = r#"{"materials": [
+        {"name": "glue", "density": 5, "radius": 0.1, "cohesion": 0.5, "flags": ["Hidden"]},
+        {"name": "rubber", "density": 2, "radius": 0.2, "restitution": 0.9}
+    ]}"#;
+    let mut simulation = boxed(&[])
+        .with_materials(MaterialRegistry::from_json(json).unwrap());
+    simulation.spawn_material = 1;
+    simulation.change_number(10);
+    assert!(simulation.particles.iter().all(|p| p.material == 1 && p.radius == 0.2));
+    assert_eq!(simulation.materials.visible().count(), 1);
+
+    let mut data = Vec::new();
+    simulation.write_scene(&mut data, SceneFormat::Binary).unwrap();
+    let loaded = Simulation::read_scene(data.as_slice()).unwrap();
+    assert_eq!(loaded.materials, simulation.materials);
+    assert_eq!(loaded.spawn_material, 1);
+}
+
+#[test]
+fn cohesion_pulls_nearby_particles_together() {
+    let json = r#"{"materials": [{"name": "clay", "density": 30, "radius": 0.1, "cohesion": 0.3}]}"#;
+    let materials = MaterialRegistry::from_json(json).unwrap();
+    let particles = [materials.particle(0, vec2(0., 0.)), materials.particle(0, vec2(0.21, 0.))];
+    let mut simulation = world(Constraint::Open(vec2(-10., -10.), vec2(10., 10.)), &particles)
+        .with_materials(materials)
+        .with_parameters(Parameters { gravity: vec2(0., 0.), ..Parameters::default() });
+    for _ in 0..20 {
+        simulation.solve(0.01);
+    }
+    let distance = simulation.particles[0].pos.distance(simulation.particles[1].pos);
+    assert!(distance < 0.205, "particles are {distance} apart");
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use common::{boxed, chain};
+use glam::vec2;
+use verlet_integration::solver::{Parameters, PARTICLE_SIZE};
+use verlet_integration::{Particle, ParticleMode};
+
+#[test]
+fn pinned_particles_anchor_a_rope() {
+    let mut simulation = chain(6, 0.3);
+    simulation.set_mode(0, ParticleMode::Pinned);
+    for _ in 0..500 {
+        simulation.solve(0.01);
+    }
+    assert_eq!(simulation.particles[0].pos, vec2(0., 5.));
+    let end = simulation.particles[5].pos;
+    assert!(end.y < 4.5 && end.distance(vec2(0., 5.)) <= 1.51, "rope end at {end}");
+    assert!((simulation.particles[1].pos.distance(vec2(0., 5.)) - 0.3).abs() < 0.01);
+}
+
+#[test]
+fn pinned_particles_ignore_collisions_and_walls() {
+    let particles = [Particle::null().place(vec2(0., -1.)), Particle::null().place(vec2(0., 1.)), Particle::null().place(vec2(0.05, 1.1))];
+    let mut simulation = boxed(&particles);
+    simulation.set_mode(0, ParticleMode::Pinned);
+    simulation.set_mode(1, ParticleMode::Pinned);
+    for _ in 0..100 {
+        simulation.solve(0.01);
+    }
+    assert_eq!(simulation.particles[0].pos, vec2(0., -1.));
+    assert_eq!(simulation.particles[1].pos, vec2(0., 1.));
+    assert!(simulation.particles[2].pos.distance(vec2(0., 1.)) >= 2. * PARTICLE_SIZE - 1e-4);
+}
+
+#[test]
+fn kinematic_particles_push_without_being_pushed() {
+    let parameters = Parameters { gravity: vec2(0., 0.), ..Parameters::default() };
+    let mut simulation = boxed(&[Particle::null().place(vec2(-1., 5.)), Particle::null().place(vec2(0., 5.))]).with_parameters(parameters);
+    simulation.set_mode(0, ParticleMode::Kinematic);
+    for step in 1..=100 {
+        let pos = vec2(-1. + 0.02 * step as f32, 5.);
+        simulation.drive(0, pos);
+        simulation.solve(0.01);
+        assert_eq!(simulation.particles[0].pos, pos);
+    }
+    assert!(simulation.particles[1].pos.x >= 1. + 2. * PARTICLE_SIZE - 0.03);
+}
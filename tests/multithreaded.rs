@@ -0,0 +1,36 @@
+use verlet_integration::multithreaded::{pair_mut, split_at_bounds, AccessChecker};
+
+#[test]
+fn split_at_bounds_yields_disjoint_slices() {
+    let mut data: Vec<usize> = (0..10).collect();
+    let slices = split_at_bounds(&mut data, &[0, 3, 3, 7, 10]);
+    let lens: Vec<usize> = slices.iter().map(|s| s.len()).collect();
+    assert_eq!(lens, [3, 0, 4, 3]);
+    assert_eq!(slices[2], [3, 4, 5, 6]);
+}
+
+#[test]
+fn pair_mut_returns_elements_in_argument_order() {
+    let mut data = [1, 2, 3, 4];
+    let (a, b) = pair_mut(&mut data, 3, 1);
+    assert_eq!((*a, *b), (4, 2));
+    std::mem::swap(a, b);
+    assert_eq!(data, [1, 4, 3, 2]);
+}
+
+#[test]
+fn access_checker_allows_repeated_access_by_one_task() {
+    let mut checker = AccessChecker::new(4);
+    checker.claim(1, 7);
+    checker.claim(1, 7);
+    checker.reset();
+    checker.claim(1, 8);
+}
+
+#[test]
+#[should_panic(expected = "touched by tasks 0 and 1")]
+fn access_checker_detects_shared_elements() {
+    let checker = AccessChecker::new(4);
+    checker.claim(2, 0);
+    checker.claim(2, 1);
+}
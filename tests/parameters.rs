@@ -0,0 +1,35 @@
+mod common;
+
+use common::world;
+use glam::vec2;
+use verlet_integration::solver::{ConstraintOrder, Parameters};
+use verlet_integration::{Constraint, Particle, Simulation};
+
+fn falling(parameters: Parameters) -> Simulation {
+    world(Constraint::Open(vec2(-10., -10.), vec2(10., 10.)), &[Particle::null().place(vec2(0., 0.))]).with_parameters(parameters)
+}
+
+#[test]
+fn gravity_and_damping_are_taken_from_the_parameters() {
+    let mut zero_g = falling(Parameters { gravity: vec2(0., 0.), ..Parameters::default() });
+    let mut sideways = falling(Parameters { gravity: vec2(1., 0.), ..Parameters::default() });
+    let mut undamped = falling(Parameters { damping: 0., ..Parameters::default() });
+    let mut damped = falling(Parameters::default());
+    for _ in 0..20 {
+        for simulation in [&mut zero_g, &mut sideways, &mut undamped, &mut damped] {
+            simulation.step_frame();
+        }
+    }
+    assert_eq!(zero_g.particles[0].pos, vec2(0., 0.));
+    assert!(sideways.particles[0].pos.x > 0. && sideways.particles[0].pos.y == 0.);
+    assert!(undamped.particles[0].pos.y < damped.particles[0].pos.y);
+}
+
+#[test]
+fn parameters_are_saved_with_the_scene() {
+    let parameters = Parameters { gravity: vec2(0.5, 2.), damping: 1.5, sub_ticks: 3, frame_dt: 0.05, iterations: 4, constraint_order: ConstraintOrder::Coloured, sort_interval: 50 };
+    let simulation = falling(parameters);
+    let mut data = Vec::new();
+    simulation.write_scene(&mut data, verlet_integration::SceneFormat::Json).unwrap();
+    assert_eq!(Simulation::read_scene(data.as_slice()).unwrap().parameters, parameters);
+}
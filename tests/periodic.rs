@@ -0,0 +1,60 @@
+mod common;
+
+use common::{launched, world};
+use glam::{vec2, BVec2};
+use verlet_integration::solver::{BroadPhase, Parameters, PARTICLE_SIZE};
+use verlet_integration::{Constraint, Particle, Simulation};
+
+fn periodic(axes: BVec2, particles: &[Particle]) -> Simulation {
+    let parameters = Parameters { gravity: vec2(0., 0.), damping: 0., ..Parameters::default() };
+    world(Constraint::Periodic(vec2(0., 0.), vec2(4., 4.), axes), particles).with_parameters(parameters)
+}
+
+#[test]
+fn particles_leaving_one_side_reappear_on_the_other() {
+    let moving = launched(Particle::null(), vec2(3.95, 3.95), vec2(0.02, 0.03));
+    let mut simulation = periodic(BVec2::TRUE, &[moving]);
+    for _ in 0..5 {
+        simulation.solve(0.01);
+    }
+    let p = simulation.particles[0];
+    assert!(p.pos.x < 0.1 && p.pos.y < 0.15, "wrapped to {}", p.pos);
+    assert!((p.pos - p.pos_old).distance(vec2(0.02, 0.03)) < 1e-5);
+
+    // the other axis keeps its walls
+    let mut falling = periodic(BVec2::new(true, false), &[Particle::null().place(vec2(3.95, 1.))]);
+    falling.parameters.gravity = vec2(5., -10.);
+    for _ in 0..200 {
+        falling.solve(0.01);
+    }
+    let p = falling.particles[0];
+    assert!((p.pos.y - PARTICLE_SIZE).abs() < 1e-4 && (0. ..4.).contains(&p.pos.x), "at {}", p.pos);
+}
+
+#[test]
+fn particles_collide_across_the_seam() {
+    for broad_phase in [BroadPhase::Grid, BroadPhase::SpatialHash] {
+        let particles = [Particle::null().place(vec2(3.95, 2.)), Particle::null().place(vec2(0.05, 2.02))];
+        let mut simulation = periodic(BVec2::new(true, false), &particles).with_broad_phase(broad_phase);
+        simulation.solve(0.01);
+        let (a, b) = (simulation.particles[0].pos, simulation.particles[1].pos);
+        let constraint = simulation.constraint;
+        assert!(constraint.delta(a, b).length() >= 2. * PARTICLE_SIZE - 1e-5, "{a} {b}");
+        // pushed apart across the seam, not through the whole box
+        assert!(a.x < 3.95 && b.x > 0.05, "{a} {b}");
+    }
+}
+
+#[test]
+fn connections_use_the_minimum_image_distance() {
+    let particles = [Particle::null().place(vec2(3.8, 2.)), Particle::null().place(vec2(0.4, 2.))];
+    let mut simulation = periodic(BVec2::TRUE, &particles);
+    simulation.add_rib(0, 1, 0.5);
+    simulation.add_spring(0, 1, 0.1);
+    for _ in 0..20 {
+        simulation.solve(0.01);
+    }
+    let (a, b) = (simulation.particles[0].pos, simulation.particles[1].pos);
+    assert!((simulation.constraint.delta(a, b).length() - 0.5).abs() < 1e-3, "{a} {b}");
+    assert!(a.x > 3. && b.x < 1., "the link did not pull them through the box: {a} {b}");
+}
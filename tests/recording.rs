@@ -0,0 +1,144 @@
+mod common;
+
+use common::{boxed, world};
+use glam::vec2;
+use verlet_integration::material::TEXTURE_COUNT;
+use verlet_integration::recording::{Input, Player, Recorder, Recording};
+use verlet_integration::solver::Parameters;
+use verlet_integration::{Collider, Connection, Constraint, Link, Particle, ParticleMode};
+
+#[test]
+fn replay_matches_the_recorded_run_in_both_directions() {
+    let mut simulation = boxed(&[])
+    .with_seed(7);
+    let mut recorder = Recorder::new(&simulation, 16).unwrap();
+    let mut hashes = vec![simulation.state_hash()];
+    for step in 0..100 {
+        if step % 30 == 0 {
+            let number = simulation.particles.len() + 50;
+            recorder.record(&mut simulation, Input::ChangeNumber(number)).unwrap();
+        }
+        recorder.record(&mut simulation, Input::Solve(0.01)).unwrap();
+        hashes.push(simulation.state_hash());
+    }
+
+    let path = std::env::temp_dir().join(format!("verlet-recording-{}.vrec", std::process::id()));
+    recorder.finish().save(&path).unwrap();
+    let recording = Recording::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(recording.steps(), 100);
+
+    let mut player = Player::new(recording).unwrap();
+    while player.step_forward().unwrap() {
+        assert_eq!(player.simulation.state_hash(), hashes[player.step()]);
+    }
+    for step in [73, 5, 40, 41, 100, 0] {
+        player.seek(step).unwrap();
+        assert_eq!(player.step(), step);
+        assert_eq!(player.simulation.state_hash(), hashes[step], "mismatch after seeking to {step}");
+    }
+}
+
+#[test]
+fn replay_matches_after_the_constraint_changes() {
+    let mut simulation = boxed(&[])
+    .with_seed(3);
+    let mut recorder = Recorder::new(&simulation, 16).unwrap();
+    let mut hashes = vec![simulation.state_hash()];
+    recorder.record(&mut simulation, Input::ChangeNumber(300)).unwrap();
+    for step in 0..35 {
+        if step == 5 {
+            let constraint = Constraint::Box(vec2(-20., 0.), vec2(20., 30.));
+            recorder.record(&mut simulation, Input::SetConstraint(constraint)).unwrap();
+        }
+        recorder.record(&mut simulation, Input::Solve(0.01)).unwrap();
+        hashes.push(simulation.state_hash());
+    }
+
+    let mut player = Player::new(recorder.finish()).unwrap();
+    for step in [35, 20, 6, 35] {
+        player.seek(step).unwrap();
+        assert_eq!(player.simulation.state_hash(), hashes[step], "mismatch after seeking to {step}");
+    }
+}
+
+#[test]
+fn inputs_pointing_past_the_simulation_are_rejected() {
+    let mut simulation = boxed(&[]);
+    let mut recorder = Recorder::new(&simulation, 16).unwrap();
+    recorder.record(&mut simulation, Input::ChangeNumber(3)).unwrap();
+    for input in [
+        Input::RemoveParticles(vec![0, 3]),
+        Input::SetMode(3, ParticleMode::Pinned),
+        Input::Drive(0, vec2(0., 0.)),
+        Input::MoveCollider(0, vec2(1., 0.), 0.),
+        Input::AddConnection(Connection::new(1, 1, Link::Rigid(1.))),
+        Input::SetSpawnMaterial(100),
+    ] {
+        assert!(recorder.record(&mut simulation, input.clone()).is_err(), "{input:?} was accepted");
+    }
+    assert_eq!(simulation.particles.len(), 3);
+    assert_eq!(recorder.finish().inputs().len(), 1);
+}
+
+#[test]
+fn inputs_with_values_a_scene_would_be_rejected_for_are_rejected() {
+    let mut simulation = boxed(&[]);
+    let mut recorder = Recorder::new(&simulation, 16).unwrap();
+    let before = simulation.state_hash();
+    for input in [
+        Input::SetConstraint(Constraint::Box(vec2(10., 0.), vec2(-10., 10.))),
+        Input::SetParameters(Parameters { sub_ticks: 0, ..Parameters::default() }),
+        Input::SetParameters(Parameters { iterations: 0, ..Parameters::default() }),
+        Input::SetParameters(Parameters { frame_dt: f32::NAN, ..Parameters::default() }),
+        Input::SetParameters(Parameters { gravity: vec2(0., f32::INFINITY), ..Parameters::default() }),
+        Input::SetKillPlane(Some(f32::NAN)),
+        Input::AddCollider(Collider::circle(vec2(0., 0.), -1.)),
+        Input::AddParticle(Particle { mass: 0., ..Particle::null() }),
+        Input::AddParticle(Particle { radius: f32::NAN, ..Particle::null() }),
+        Input::AddParticle(Particle { material: 100, ..Particle::null() }),
+        Input::AddParticle(Particle { texture: TEXTURE_COUNT, ..Particle::null() }),
+        Input::Solve(f32::NAN),
+    ] {
+        assert!(recorder.record(&mut simulation, input.clone()).is_err(), "{input:?} was accepted");
+    }
+    assert_eq!(simulation.state_hash(), before);
+    assert_eq!(simulation.parameters, Parameters::default());
+    assert!(simulation.kill_plane.is_none());
+    assert_eq!(recorder.finish().inputs().len(), 0);
+}
+
+#[test]
+fn corrupted_recordings_fail_to_replay() {
+    let mut simulation = boxed(&[]);
+    let mut recorder = Recorder::new(&simulation, 16).unwrap();
+    recorder.record(&mut simulation, Input::ChangeNumber(3)).unwrap();
+    recorder.record(&mut simulation, Input::SetMode(2, ParticleMode::Pinned)).unwrap();
+    recorder.record(&mut simulation, Input::Solve(0.01)).unwrap();
+
+    // as if the recording was edited by hand
+    let mut json = serde_json::to_value(recorder.finish()).unwrap();
+    json["inputs"][1]["SetMode"][0] = 7.into();
+    let recording: Recording = serde_json::from_value(json).unwrap();
+
+    let mut player = Player::new(recording.clone()).unwrap();
+    assert!(player.step_forward().is_err());
+    let mut player = Player::new(recording).unwrap();
+    assert!(player.seek(1).is_err());
+}
+
+#[test]
+fn keyframes_of_large_scenes_are_further_apart() {
+    let keyframes = |particles| {
+        let mut simulation = world(Constraint::Box(vec2(-20., 0.), vec2(20., 40.)), &[]);
+        simulation.change_number(particles);
+        let mut recorder = Recorder::new(&simulation, 1).unwrap();
+        for _ in 0..40 {
+            recorder.record(&mut simulation, Input::Solve(0.01)).unwrap();
+        }
+        serde_json::to_value(recorder.finish()).unwrap()["keyframes"].as_array().unwrap().len()
+    };
+    assert_eq!(keyframes(10), 41);
+    // about 72 bytes per particle, a keyframe every ~17 steps
+    assert_eq!(keyframes(2000), 3);
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::{chain, world};
+use glam::vec2;
+use verlet_integration::{Constraint, Simulation};
+
+/// A [`chain`] of 6 particles with an angle at each inner particle.
+fn angled_chain() -> Simulation {
+    let mut simulation = chain(6, 1.);
+    for i in 1..5 {
+        simulation.add_angle(i - 1, i, i + 1, 1.);
+    }
+    simulation
+}
+
+#[test]
+fn removing_particles_remaps_connections_and_angles() {
+    let mut simulation = angled_chain();
+    let map = simulation.remove_particles([4, 1]);
+    assert_eq!(map, [Some(0), None, Some(1), Some(2), None, Some(3)]);
+    let xs: Vec<f32> = simulation.particles.iter().map(|p| p.pos.x).collect();
+    assert_eq!(xs, [0., 2., 3., 5.]);
+
+    // only the link between the old particles 2 and 3 survives
+    let links: Vec<_> = simulation.connections.iter().map(|c| (c.i, c.j)).collect();
+    assert_eq!(links, [(1, 2)]);
+    assert!(simulation.angles.is_empty());
+
+    let mut simulation = angled_chain();
+    simulation.remove_particles([0]);
+    let links: Vec<_> = simulation.connections.iter().map(|c| (c.i, c.j)).collect();
+    assert_eq!(links, [(0, 1), (1, 2), (2, 3), (3, 4)]);
+    let angles: Vec<_> = simulation.angles.iter().map(|a| (a.i, a.j, a.k)).collect();
+    assert_eq!(angles, [(0, 1, 2), (1, 2, 3), (2, 3, 4)]);
+    for c in simulation.connections.iter() {
+        assert_eq!(simulation.particles[c.i].pos.distance(simulation.particles[c.j].pos), 1.);
+    }
+}
+
+#[test]
+fn indices_past_the_last_particle_are_ignored() {
+    let mut simulation = angled_chain();
+    let map = simulation.remove_particles([6, 2, usize::MAX]);
+    assert_eq!(map, [Some(0), Some(1), None, Some(2), Some(3), Some(4)]);
+    assert_eq!(simulation.particles.len(), 5);
+}
+
+#[test]
+fn lowering_the_particle_count_drops_links_to_the_removed_tail() {
+    let mut simulation = angled_chain();
+    simulation.change_number(3);
+    assert_eq!(simulation.particles.len(), 3);
+    assert_eq!(simulation.connections.len(), 2);
+    assert_eq!(simulation.angles.len(), 1);
+}
+
+#[test]
+fn raising_the_particle_count_in_a_thin_box_spawns_on_its_centre_line() {
+    let mut simulation = world(Constraint::Box(vec2(0., 0.), vec2(10., 0.1)), &[]);
+    simulation.change_number(3);
+    assert_eq!(simulation.particles.len(), 3);
+    assert!(simulation.particles.iter().all(|p| p.pos.y == 0.05 && p.pos.x > 0. && p.pos.x < 10.));
+}
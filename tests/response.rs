@@ -0,0 +1,73 @@
+mod common;
+
+use common::{boxed, launched, world};
+use glam::vec2;
+use verlet_integration::particle::Particle;
+use verlet_integration::solver::{Parameters, PARTICLE_SIZE};
+use verlet_integration::{Constraint, Link, Simulation};
+
+fn highest_after_bounce(restitution: f32) -> f32 {
+    let mut simulation = boxed(&[Particle { restitution, ..Particle::null() }.place(vec2(0., 1.))])
+        .with_parameters(Parameters { damping: 0., ..Parameters::default() });
+    let mut highest = 0f32;
+    let mut bounced = false;
+    for _ in 0..400 {
+        simulation.solve(0.01);
+        let p = simulation.particles[0];
+        bounced |= p.pos.y <= PARTICLE_SIZE + 1e-4;
+        if bounced {
+            highest = highest.max(p.pos.y);
+        }
+    }
+    highest
+}
+
+#[test]
+fn restitution_makes_particles_bounce_off_walls() {
+    assert!(highest_after_bounce(0.) < 0.2);
+    assert!(highest_after_bounce(0.9) > 0.6);
+}
+
+#[test]
+fn friction_stops_sliding_particles() {
+    let slide = |friction| {
+        let sliding = launched(Particle { friction, ..Particle::null() }, vec2(0., PARTICLE_SIZE), vec2(0.01, 0.));
+        let mut simulation = boxed(&[sliding]).with_parameters(Parameters { damping: 0., ..Parameters::default() });
+        for _ in 0..200 {
+            simulation.solve(0.01);
+        }
+        simulation.particles[0].pos.x
+    };
+    assert!(slide(0.) > 1.9);
+    assert!(slide(1.) < 0.6);
+}
+
+#[test]
+fn restitution_exchanges_momentum_between_particles() {
+    let collide = |restitution| {
+        let particle = Particle { restitution, ..Particle::null() };
+        let particles = [launched(particle, vec2(-0.5, 0.), vec2(0.01, 0.)), particle.place(vec2(0., 0.))];
+        let mut simulation = world(Constraint::Open(vec2(-10., -10.), vec2(10., 10.)), &particles)
+            .with_parameters(Parameters { gravity: vec2(0., 0.), damping: 0., ..Parameters::default() });
+        for _ in 0..200 {
+            simulation.solve(0.01);
+        }
+        simulation.particles[0].pos.x
+    };
+    // an inelastic hit lets both move on together, an elastic one stops the first particle
+    assert!(collide(0.) > 0.3);
+    assert!(collide(1.) < -0.1);
+}
+
+#[test]
+fn coincident_particles_are_pushed_apart() {
+    let (mut a, mut b) = (Particle::null().place(vec2(1., 1.)), Particle::null().place(vec2(1., 1.)));
+    Simulation::resolve_collision(&mut a, &mut b);
+    assert!(a.pos.is_finite() && a.pos_old.is_finite() && b.pos.is_finite(), "{} {}", a.pos, b.pos);
+    assert!((a.pos.distance(b.pos) - 2. * PARTICLE_SIZE).abs() < 1e-5);
+
+    let (mut a, mut b) = (Particle::null().place(vec2(1., 1.)), Particle::null().place(vec2(1., 1.)));
+    Simulation::resolve_connection(&mut a, &mut b, Link::Rigid(0.5), 0.01);
+    assert!(a.pos.is_finite() && b.pos.is_finite(), "{} {}", a.pos, b.pos);
+    assert!((a.pos.distance(b.pos) - 0.5).abs() < 1e-5);
+}
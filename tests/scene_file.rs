@@ -0,0 +1,79 @@
+mod common;
+
+use common::chain;
+use glam::vec2;
+use verlet_integration::material::TEXTURE_COUNT;
+use verlet_integration::scene_file::SCENE_MAGIC;
+use verlet_integration::{Particle, SceneFormat, Simulation};
+
+fn round_trip(simulation: &Simulation, format: SceneFormat) -> anyhow::Result<Simulation> {
+    let mut data = Vec::new();
+    simulation.write_scene(&mut data, format).unwrap();
+    assert_eq!(data.starts_with(SCENE_MAGIC), format == SceneFormat::Binary);
+    Simulation::read_scene(data.as_slice())
+}
+
+#[test]
+fn scenes_survive_a_round_trip_in_both_formats() {
+    let simulation = chain(3, 1.);
+    for format in [SceneFormat::Json, SceneFormat::Binary] {
+        let loaded = round_trip(&simulation, format).unwrap();
+        assert_eq!(loaded.cell_size, simulation.cell_size);
+        assert_eq!(loaded.constraint.bounds(), simulation.constraint.bounds());
+        assert_eq!(loaded.connections.len(), 2);
+        let positions: Vec<_> = loaded.particles.iter().map(|p| p.pos).collect();
+        assert_eq!(positions, [vec2(0., 5.), vec2(1., 5.), vec2(2., 5.)]);
+    }
+}
+
+#[test]
+fn dangling_connections_are_rejected() {
+    let mut simulation = chain(3, 1.);
+    simulation.add_rib(2, 3, 1.);
+    for format in [SceneFormat::Json, SceneFormat::Binary] {
+        let error = round_trip(&simulation, format).err().expect("the scene should be rejected");
+        assert!(error.to_string().contains("non-existent particle"), "{error}");
+    }
+}
+
+#[test]
+fn self_connections_are_rejected() {
+    let mut simulation = chain(3, 1.);
+    simulation.add_rib(1, 1, 1.);
+    for format in [SceneFormat::Json, SceneFormat::Binary] {
+        let error = round_trip(&simulation, format).err().expect("the scene should be rejected");
+        assert!(error.to_string().contains("to itself"), "{error}");
+    }
+}
+
+fn assert_particle_rejected(formats: &[SceneFormat], edit: impl Fn(&mut Particle), message: &str) {
+    let mut simulation = chain(3, 1.);
+    edit(&mut simulation.particles[1]);
+    for &format in formats {
+        let error = round_trip(&simulation, format).err().expect("the scene should be rejected");
+        assert!(format!("{error:#}").contains(message), "{error:#}");
+    }
+}
+
+#[test]
+fn particles_with_invalid_properties_are_rejected() {
+    let both = [SceneFormat::Json, SceneFormat::Binary];
+    assert_particle_rejected(&both, |p| p.mass = 0., "positive mass");
+    assert_particle_rejected(&both, |p| p.radius = -1., "positive mass and radius");
+    assert_particle_rejected(&both, |p| p.texture = TEXTURE_COUNT, "textures exist");
+    // JSON has no NaN or infinity, they are written as null and fail to parse
+    assert_particle_rejected(&[SceneFormat::Binary], |p| p.mass = f32::NAN, "positive mass");
+    assert_particle_rejected(&[SceneFormat::Binary], |p| p.radius = f32::INFINITY, "positive mass and radius");
+    assert_particle_rejected(&[SceneFormat::Binary], |p| p.pos.x = f32::NAN, "non-finite position");
+    assert_particle_rejected(&[SceneFormat::Binary], |p| p.pos_old.y = f32::NEG_INFINITY, "non-finite position");
+}
+
+#[test]
+fn backwards_frames_are_rejected() {
+    let mut simulation = chain(3, 1.);
+    simulation.parameters.frame_dt = -0.01;
+    for format in [SceneFormat::Json, SceneFormat::Binary] {
+        let error = round_trip(&simulation, format).err().expect("the scene should be rejected");
+        assert!(error.to_string().contains("invalid parameters"), "{error}");
+    }
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::{dropped, run};
+use glam::{vec2, Vec2};
+use verlet_integration::solver::PARTICLE_SIZE;
+use verlet_integration::{Collider, DistanceField, Sdf};
+
+#[test]
+fn baked_fields_match_the_analytic_distance() {
+    let sdf = Sdf::Circle { center: vec2(0., 0.), radius: 2. }.union(Sdf::Rect { center: vec2(3., 0.), half_size: vec2(1., 0.5) });
+    let field = DistanceField::from_sdf(&sdf, vec2(-4., -4.), vec2(5., 4.), 0.1).unwrap();
+    for pos in [vec2(0., 3.), vec2(0.5, 0.3), vec2(3.2, 0.9), vec2(-2.5, -1.7), vec2(4.4, 0.)] {
+        let (distance, normal) = field.sample(pos);
+        assert!((distance - sdf.distance(pos)).abs() < 0.02, "{pos}: {distance} vs {}", sdf.distance(pos));
+        assert!((normal.length() - 1.).abs() < 1e-4);
+    }
+    // outside of the grid the distance to it is added
+    assert!((field.sample(vec2(0., 6.)).0 - 4.).abs() < 0.02);
+}
+
+#[test]
+fn image_fields_find_the_boundary_between_pixels() {
+    // the left half of the mask is solid
+    let (width, height) = (20, 10);
+    let solid: Vec<bool> = (0..width * height).map(|k| k % width < 10).collect();
+    let field = DistanceField::from_mask(&solid, width, height, vec2(0., 0.), 0.5).unwrap();
+    for x in [2., 4.25, 4.5, 6., 8.] {
+        let (distance, normal) = field.sample(vec2(x, 2.));
+        assert!((distance - (x - 4.75)).abs() < 1e-4, "{x}: {distance}");
+        assert_eq!(normal, vec2(1., 0.));
+    }
+
+    let path = std::env::temp_dir().join(format!("verlet-sdf-{}.png", std::process::id()));
+    let image = image::GrayImage::from_fn(width as u32, height as u32, |x, y| {
+        // dark bottom row in the image is the lowest row of the field
+        image::Luma([if y == height as u32 - 1 || x < 10 { 0 } else { 255 }])
+    });
+    image.save(&path).unwrap();
+    let loaded = DistanceField::from_image(&path, vec2(0., 0.), 0.5).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(loaded.sample(vec2(7., 0.)).0 < 0.);
+    assert!(loaded.sample(vec2(7., 1.)).0 > 0.);
+    assert!(DistanceField::from_image("does-not-exist.png", Vec2::ZERO, 1.).is_err());
+}
+
+#[test]
+fn particles_slide_into_carved_shapes() {
+    // a block with a round pit carved into its top
+    let sdf = Sdf::Rect { center: vec2(0., -1.), half_size: vec2(4., 1.) }.difference(Sdf::Circle { center: vec2(0., 0.), radius: 1.5 });
+    let collider = Collider::sdf(&sdf, vec2(-5., -3.), vec2(5., 2.), 0.05).unwrap();
+    let mut simulation = dropped(vec![collider], vec2(-1., 1.));
+    run(&mut simulation, 100);
+    // swinging in the pit, touching its surface
+    let pos = simulation.particles[0].pos;
+    assert!((pos.length() - 1.5 + PARTICLE_SIZE).abs() < 0.03 && pos.y < -0.5, "at {pos}");
+}
+
+#[test]
+fn degenerate_fields_are_rejected() {
+    let circle = Sdf::Circle { center: vec2(0., 0.), radius: 1. };
+    // inverted and empty bounds
+    assert!(DistanceField::from_sdf(&circle, vec2(2., 2.), vec2(-2., -2.), 0.1).is_err());
+    assert!(DistanceField::from_sdf(&circle, vec2(-2., 0.), vec2(2., 0.), 0.1).is_err());
+    assert!(Collider::sdf(&circle, vec2(2., -2.), vec2(-2., 2.), 0.1).is_err());
+    // cell sizes
+    for cell_size in [0., -0.1, f32::NAN, f32::INFINITY] {
+        assert!(DistanceField::from_sdf(&circle, vec2(-2., -2.), vec2(2., 2.), cell_size).is_err(), "{cell_size}");
+        assert!(DistanceField::from_mask(&[true; 4], 2, 2, Vec2::ZERO, cell_size).is_err(), "{cell_size}");
+    }
+    // fields one sample wide or tall
+    assert!(DistanceField::from_mask(&[true; 5], 1, 5, Vec2::ZERO, 0.5).is_err());
+    assert!(DistanceField::from_mask(&[true; 5], 5, 1, Vec2::ZERO, 0.5).is_err());
+    assert!(DistanceField::from_mask(&[true; 3], 2, 2, Vec2::ZERO, 0.5).is_err());
+    let path = std::env::temp_dir().join(format!("verlet-sdf-line-{}.png", std::process::id()));
+    image::GrayImage::from_pixel(8, 1, image::Luma([0])).save(&path).unwrap();
+    let line = DistanceField::from_image(&path, Vec2::ZERO, 0.5);
+    std::fs::remove_file(&path).unwrap();
+    assert!(line.is_err());
+    // fields built by hand are checked when they become colliders
+    let thin = DistanceField { origin: Vec2::ZERO, cell_size: 1., width: 1, height: 1, distances: vec![0.] };
+    assert!(Collider::field(thin).is_err());
+}
@@ -0,0 +1,58 @@
+use glam::vec2;
+use verlet_integration::solver::Parameters;
+use verlet_integration::{Constraint, Particle, SceneFormat, Simulation};
+
+/// Particles in the middle of the unit cells of the grid, stored in an order that jumps across the box.
+fn scattered() -> Simulation {
+    let particles: Vec<_> = (0..64)
+        .map(|k| {
+            let k = (k * 37) % 64;
+            Particle::null().place(vec2((k % 8) as f32, (k / 8) as f32))
+        })
+        .collect();
+    let mut simulation = Simulation::new(Constraint::Box(vec2(-0.5, -0.5), vec2(8.5, 8.5)), 1., &particles, &[]);
+    simulation.add_rib(0, 1, 1.);
+    simulation.add_angle(2, 3, 4, 1.);
+    simulation
+}
+
+#[test]
+fn sorting_keeps_links_and_ids_on_their_particles() {
+    let mut simulation = scattered();
+    let positions: Vec<_> = simulation.particles.iter().map(|p| p.pos).collect();
+    let ids: Vec<_> = (0..64).map(|i| simulation.id_of(i).unwrap()).collect();
+
+    let map = simulation.sort_particles();
+    for (i, new) in map.iter().enumerate() {
+        let new = new.unwrap();
+        assert_eq!(simulation.particles[new].pos, positions[i]);
+        assert_eq!(simulation.index_of(ids[i]), Some(new));
+    }
+    let c = simulation.connections[0];
+    assert_eq!((c.i, c.j), (map[0].unwrap(), map[1].unwrap()));
+    let a = simulation.angles[0];
+    assert_eq!((a.i, a.j, a.k), (map[2].unwrap(), map[3].unwrap(), map[4].unwrap()));
+
+    // the first quarter of the sorted particles fills the bottom left quadrant
+    for p in simulation.particles[..16].iter() {
+        assert!(p.pos.x < 4. && p.pos.y < 4.);
+    }
+}
+
+#[test]
+fn periodic_sorting_continues_identically_after_loading() {
+    let parameters = Parameters { sort_interval: 3, ..Parameters::default() };
+    let mut simulation = scattered().with_parameters(parameters).with_seed(1);
+    for _ in 0..4 {
+        simulation.solve(0.01);
+    }
+    let mut data = Vec::new();
+    simulation.write_scene(&mut data, SceneFormat::Binary).unwrap();
+    let mut loaded = Simulation::read_scene(data.as_slice()).unwrap();
+    for _ in 0..5 {
+        simulation.solve(0.01);
+        loaded.solve(0.01);
+    }
+    assert_eq!(simulation.steps, 9);
+    assert_eq!(simulation.state_hash(), loaded.state_hash());
+}